@@ -0,0 +1,80 @@
+//! Benchmarks for distributing received packets through the ECS.
+
+use std::{io::Cursor, sync::mpsc};
+
+use bevy_ecs::prelude::*;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use optical::net::{packet_broadcaster, NetworkConnected, PacketReceived};
+use optical_protocol::{
+    format::{
+        serializer,
+        tags::{LoginPacket, PlayPacket, StatusPacket, VoidPacket},
+    },
+    packets::status::serverbound::PingRequest,
+    server::{Connection, ProtocolState},
+};
+use tokio::sync::mpsc::unbounded_channel;
+
+#[derive(StageLabel)]
+struct UpdateLabel;
+
+/// Creates a world with `connections` simulated clients in the status state, each with a ping
+/// request waiting to be broadcasted. The senders are returned so the clients stay connected.
+fn setup(connections: usize) -> (World, Schedule, Vec<mpsc::Sender<Cursor<Vec<u8>>>>) {
+    let mut world = World::new();
+    world.insert_resource(Events::<PacketReceived<dyn VoidPacket>>::default());
+    world.insert_resource(Events::<PacketReceived<dyn StatusPacket>>::default());
+    world.insert_resource(Events::<PacketReceived<dyn LoginPacket>>::default());
+    world.insert_resource(Events::<PacketReceived<dyn PlayPacket>>::default());
+
+    let packet = PingRequest { payload: 0 };
+    let bytes = serializer::to_bytes(&packet, packet.packet_id()).unwrap();
+    let mut senders = Vec::with_capacity(connections);
+    for _ in 0..connections {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(Cursor::new(bytes.clone())).unwrap();
+        world.spawn(NetworkConnected::from(Connection {
+            state: ProtocolState::Status,
+            packets: receiver,
+            outgoing: unbounded_channel().0,
+            address: ([127, 0, 0, 1], 25565).into(),
+            player: None,
+        }));
+        senders.push(sender);
+    }
+
+    let mut schedule = Schedule::default();
+    schedule.add_stage(
+        UpdateLabel,
+        SystemStage::parallel().with_system(packet_broadcaster),
+    );
+
+    (world, schedule, senders)
+}
+
+fn broadcast(c: &mut Criterion) {
+    let mut group = c.benchmark_group("packet_broadcaster");
+
+    for connections in [1, 100, 1000, 10000] {
+        group.throughput(Throughput::Elements(connections as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(connections),
+            &connections,
+            |b, &connections| {
+                b.iter_batched(
+                    || setup(connections),
+                    |(mut world, mut schedule, senders)| {
+                        schedule.run(&mut world);
+                        (world, senders)
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, broadcast);
+criterion_main!(benches);
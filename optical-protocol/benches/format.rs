@@ -0,0 +1,184 @@
+//! Benchmarks for serializing, deserializing and framing packets.
+
+use std::io::Cursor;
+
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use optical_protocol::{
+    format::{
+        deserializer, serializer,
+        tags::{ClientLoginPacket, VoidPacket},
+        types::{read_var_int, write_var_int, Bytes},
+    },
+    packets::{login::clientbound::LoginPluginRequest, void::serverbound::Handshake},
+    server::split_packet,
+};
+
+fn handshake() -> Handshake {
+    return Handshake {
+        protocol_version: 761.into(),
+        server_address: "localhost".to_string(),
+        server_port: 25565,
+        next_state: 2.into(),
+    };
+}
+
+fn plugin_request(size: usize) -> LoginPluginRequest {
+    return LoginPluginRequest {
+        message_id: 1.into(),
+        channel: "optical:bench".to_string(),
+        data: Bytes(vec![0xab; size]),
+    };
+}
+
+fn serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("to_bytes");
+
+    let packet = handshake();
+    group.bench_function("handshake", |b| {
+        b.iter(|| serializer::to_bytes(&packet, packet.packet_id()).unwrap())
+    });
+
+    for size in [1024, 64 * 1024, 1024 * 1024] {
+        let packet = plugin_request(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("plugin_request", size), &packet, |b, p| {
+            b.iter(|| serializer::to_bytes(p, p.packet_id()).unwrap())
+        });
+    }
+
+    // Many packets written into one buffer, like a broadcast of entity updates would be.
+    for count in [16, 256, 4096] {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("batched", count), &count, |b, &count| {
+            let mut buf = BytesMut::new();
+            b.iter(|| {
+                buf.clear();
+                for _ in 0..count {
+                    serializer::encode_into(&packet, packet.packet_id(), &mut buf).unwrap();
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("from_bytes");
+
+    let packet = handshake();
+    let bytes = serializer::to_bytes(&packet, packet.packet_id()).unwrap();
+    group.bench_function("handshake", |b| {
+        b.iter_batched(
+            || Cursor::new(bytes.clone()),
+            |mut input| deserializer::from_bytes::<Handshake>(&mut input).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("handshake_generic", |b| {
+        b.iter_batched(
+            || Cursor::new(bytes.clone()),
+            |mut input| {
+                deserializer::from_bytes_generic::<Box<dyn VoidPacket>>(&mut input).unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    for size in [1024, 64 * 1024, 1024 * 1024] {
+        let packet = plugin_request(size);
+        let bytes = serializer::to_bytes(&packet, packet.packet_id()).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("plugin_request", size),
+            &bytes,
+            |b, bytes| {
+                b.iter_batched(
+                    || Cursor::new(bytes.clone()),
+                    |mut input| deserializer::from_bytes::<LoginPluginRequest>(&mut input).unwrap(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("plugin_request_generic", size),
+            &bytes,
+            |b, bytes| {
+                b.iter_batched(
+                    || Cursor::new(bytes.clone()),
+                    |mut input| {
+                        deserializer::from_bytes_generic::<Box<dyn ClientLoginPacket>>(&mut input)
+                            .unwrap()
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn var_int(c: &mut Criterion) {
+    let mut group = c.benchmark_group("var_int");
+    let values = [0, 127, 25565, 2097151, i32::MAX, -1];
+
+    group.throughput(Throughput::Elements(values.len() as u64));
+    group.bench_function("encode", |b| {
+        let mut buf = Vec::with_capacity(values.len() * 5);
+        b.iter(|| {
+            buf.clear();
+            for value in values {
+                write_var_int(&mut buf, value);
+            }
+        })
+    });
+
+    let mut encoded = vec![];
+    for value in values {
+        write_var_int(&mut encoded, value);
+    }
+    group.bench_function("decode", |b| {
+        b.iter_batched(
+            || Cursor::new(encoded.clone()),
+            |mut input| {
+                for _ in values {
+                    read_var_int(&mut input).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn framing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("split_packet");
+
+    // Many packets arriving in a single read, like a client pipelining its handshake, login and
+    // first play packets.
+    let packet = handshake();
+    let single = serializer::to_bytes(&packet, packet.packet_id()).unwrap();
+    for count in [16, 256, 4096] {
+        let pipelined = single.repeat(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(
+            BenchmarkId::new("pipelined", count),
+            &pipelined,
+            |b, input| {
+                b.iter_batched(
+                    || input.clone(),
+                    |mut buf| while split_packet(&mut buf).unwrap().is_some() {},
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, serialize, deserialize, var_int, framing);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+//! Feeds a single framed packet to [`from_bytes_generic`] for one of the packet traits. The first
+//! byte of the input picks which trait the packet is deserialized as.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use optical_protocol::format::{
+    deserializer::from_bytes_generic,
+    tags::{
+        ClientLoginPacket, ClientPlayPacket, ClientStatusPacket, ClientVoidPacket, LoginPacket,
+        PlayPacket, StatusPacket, VoidPacket,
+    },
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((selector, packet)) = data.split_first() else {
+        return;
+    };
+    let mut input = Cursor::new(packet.to_vec());
+    let _ = match selector % 8 {
+        0 => from_bytes_generic::<Box<dyn VoidPacket>>(&mut input).map(drop),
+        1 => from_bytes_generic::<Box<dyn StatusPacket>>(&mut input).map(drop),
+        2 => from_bytes_generic::<Box<dyn LoginPacket>>(&mut input).map(drop),
+        3 => from_bytes_generic::<Box<dyn PlayPacket>>(&mut input).map(drop),
+        4 => from_bytes_generic::<Box<dyn ClientVoidPacket>>(&mut input).map(drop),
+        5 => from_bytes_generic::<Box<dyn ClientStatusPacket>>(&mut input).map(drop),
+        6 => from_bytes_generic::<Box<dyn ClientLoginPacket>>(&mut input).map(drop),
+        _ => from_bytes_generic::<Box<dyn ClientPlayPacket>>(&mut input).map(drop),
+    };
+});
@@ -0,0 +1,22 @@
+//! Feeds a stream of bytes to the framing layer, as if it arrived over TCP in chunks, and splits
+//! off every complete packet.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use optical_protocol::server::split_packet;
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = vec![];
+    // Deliver the input in chunks of varying size, like reads from a socket would.
+    for chunk in data.chunks(data.first().map_or(1, |n| *n as usize + 1)) {
+        buf.extend_from_slice(chunk);
+        loop {
+            match split_packet(&mut buf) {
+                Ok(Some(_)) => continue,
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+    }
+});
@@ -0,0 +1,131 @@
+//! Prints the packets of a connection in a readable form.
+//!
+//! ```text
+//! optical-dump [--json] <capture or hex file, - for stdin>
+//! ```
+//!
+//! The input is either a capture recorded by the listener, or text holding frames as hex. Every
+//! line of hex holds one or more frames, sent serverbound, unless the line starts with `<`, which
+//! marks clientbound frames. Lines starting with `>` are serverbound, and lines starting with `#`
+//! are ignored.
+//!
+//! The protocol state is followed through the packets, starting at the handshake. Packets are
+//! printed with `Debug`, or as JSON with `--json`. Packets which don't decode are printed as hex.
+
+use std::{
+    env, fs,
+    io::{self, Cursor, Read},
+    process::ExitCode,
+};
+
+use optical_protocol::{
+    capture::{CaptureReader, MAGIC},
+    format::{deserializer::TrailingBytesMode, framing::split_packet},
+    inspect::{self, StateTracker},
+    packets::Direction,
+};
+
+const USAGE: &str = "usage: optical-dump [--json] <capture or hex file, - for stdin>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (json, path) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["--json", path] => (true, path),
+        [path] if path != "--json" => (false, path),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if let Err(e) = dump(path, json) {
+        eprintln!("optical-dump: {e}");
+        return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
+}
+
+fn dump(path: &str, json: bool) -> io::Result<()> {
+    let input = if path == "-" {
+        let mut input = vec![];
+        io::stdin().read_to_end(&mut input)?;
+        input
+    } else {
+        fs::read(path)?
+    };
+
+    let frames = if input.starts_with(&MAGIC) {
+        CaptureReader::new(Cursor::new(input))?
+            .map(|frame| frame.map(|f| (f.direction, f.data)))
+            .collect::<io::Result<_>>()?
+    } else {
+        parse_hex(&String::from_utf8_lossy(&input))?
+    };
+
+    let mut tracker = StateTracker::default();
+    for (direction, frame) in frames {
+        let arrow = match direction {
+            Direction::Serverbound => ">",
+            Direction::Clientbound => "<",
+        };
+        let state = tracker.state;
+        let mut input = Cursor::new(frame);
+        match inspect::decode_with_mode(state, direction, &mut input, TrailingBytesMode::Strict) {
+            Ok(packet) => {
+                tracker.observe(&packet);
+                let rendered = match json {
+                    true => serde_json::to_string(&packet).map_err(io::Error::other)?,
+                    false => format!("{packet:?}"),
+                };
+                println!("{arrow} {state:?} {rendered}");
+            }
+            Err(e) => {
+                println!(
+                    "{arrow} {state:?} unknown {} ({e})",
+                    inspect::hex(input.get_ref())
+                );
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// Parses lines of hex into frames, along with the direction of their line.
+fn parse_hex(text: &str) -> io::Result<Vec<(Direction, Vec<u8>)>> {
+    let mut frames = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let (direction, line) = match line.chars().next() {
+            None | Some('#') => continue,
+            Some('<') => (Direction::Clientbound, &line[1..]),
+            Some('>') => (Direction::Serverbound, &line[1..]),
+            Some(_) => (Direction::Serverbound, line),
+        };
+
+        let digits: Vec<u8> = line.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        let mut bytes = digits
+            .chunks(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .filter(|_| pair.len() == 2)
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| invalid_data(format!("line {} isn't valid hex", number + 1)))?;
+
+        while let Some(frame) = split_packet(&mut bytes).map_err(invalid_data)? {
+            frames.push((direction, frame.into_inner()));
+        }
+        if !bytes.is_empty() {
+            return Err(invalid_data(format!(
+                "line {} ends with an incomplete frame",
+                number + 1
+            )));
+        }
+    }
+    return Ok(frames);
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, error.to_string());
+}
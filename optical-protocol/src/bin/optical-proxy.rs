@@ -0,0 +1,237 @@
+//! Sits between a client and a server, and prints every packet they send each other.
+//!
+//! ```text
+//! optical-proxy <listen address> <upstream address> [--json]
+//! ```
+//!
+//! Every client connecting to the listen address gets its own connection to the upstream server,
+//! and frames are forwarded unchanged in both directions, after being decoded with the packet
+//! registries. This makes it easy to diff how our server behaves against a vanilla one.
+//!
+//! Encryption can't be forwarded, since the proxy can't read the shared secret of an encrypted
+//! connection. When the server asks for encryption, the proxy answers it itself, and asks the
+//! client for encryption with its own key, so both sides are encrypted with different secrets.
+//! The server has to be in offline mode, as the client joins a session for the proxy's key
+//! instead of the server's. Compression is turned on for both sides when the server asks for it.
+//!
+//! The proxy accepts connections itself instead of through [`server::start_with_listener`]. The
+//! listener ends the protocol on its side: it reads the handshake, runs the login with its own
+//! encryption, forwarding and login plugin channels, and only hands connections over in the
+//! Status or Play state. The proxy has to pass every frame from the handshake on to the upstream
+//! server as it is, so it can't sit behind it. It therefore has none of the listener's limits,
+//! timeouts, PROXY header handling or graceful shutdown, and is meant for debugging on a trusted
+//! network, not for exposing to the internet.
+//!
+//! [`server::start_with_listener`]: optical_protocol::server::start_with_listener
+
+use std::{env, io::Cursor, process::ExitCode};
+
+use anyhow::{bail, Result};
+use optical_protocol::{
+    client,
+    format::{
+        deserializer::TrailingBytesMode,
+        tags::{ClientLoginPacket, LoginPacket},
+    },
+    inspect::{self, Packet, StateTracker},
+    packets::{
+        login::{
+            clientbound::{EncryptionRequest, SetCompression},
+            serverbound::EncryptionResponse,
+        },
+        Direction,
+    },
+    stream::PacketStream,
+};
+use rsa::{pkcs8::EncodePublicKey, PaddingScheme, RsaPrivateKey, RsaPublicKey};
+use tokio::net::{TcpListener, TcpStream};
+
+const USAGE: &str = "usage: optical-proxy <listen address> <upstream address> [--json]";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (listen, upstream, json) = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [listen, upstream] => (listen.to_string(), upstream.to_string(), false),
+        [listen, upstream, "--json"] => (listen.to_string(), upstream.to_string(), true),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if let Err(e) = run(&listen, upstream, json).await {
+        eprintln!("optical-proxy: {e}");
+        return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
+}
+
+async fn run(listen: &str, upstream: String, json: bool) -> Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+    println!("Proxying {listen} to {upstream}");
+
+    let mut connection_count: u64 = 0;
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        connection_count += 1;
+        let id = connection_count;
+        let upstream = upstream.clone();
+        let private_key = private_key.clone();
+        tokio::spawn(async move {
+            println!("{id} connected from {peer}");
+            match proxy(id, socket, &upstream, private_key, json).await {
+                Ok(()) => println!("{id} closed"),
+                Err(e) => println!("{id} closed with error: {e}"),
+            }
+        });
+    }
+}
+
+/// The proxy's end of a connection.
+struct Connection {
+    id: u64,
+    json: bool,
+    client: PacketStream,
+    server: PacketStream,
+    tracker: StateTracker,
+    private_key: RsaPrivateKey,
+    /// The verify token sent to the client, while waiting for its encryption response.
+    verify_token: Option<Vec<u8>>,
+}
+
+async fn proxy(
+    id: u64,
+    socket: TcpStream,
+    upstream: &str,
+    private_key: RsaPrivateKey,
+    json: bool,
+) -> Result<()> {
+    let mut connection = Connection {
+        id,
+        json,
+        client: PacketStream::new(socket),
+        server: PacketStream::new(TcpStream::connect(upstream).await?),
+        tracker: StateTracker::default(),
+        private_key,
+        verify_token: None,
+    };
+
+    loop {
+        // Reading a frame is cancel safe, so whichever side is quiet loses nothing. While the
+        // client is still answering our encryption request, the server's frames are held back, as
+        // they have to be encrypted for the client.
+        let awaiting_client = connection.verify_token.is_some();
+        let (direction, frame) = tokio::select! {
+            frame = connection.client.read_frame() => (Direction::Serverbound, frame?),
+            frame = connection.server.read_frame(), if !awaiting_client => {
+                (Direction::Clientbound, frame?)
+            }
+        };
+        let frame = match frame {
+            Some(frame) => frame.into_inner(),
+            None => return Ok(()),
+        };
+        connection.forward(direction, frame).await?;
+    }
+}
+
+impl Connection {
+    async fn forward(&mut self, direction: Direction, frame: Vec<u8>) -> Result<()> {
+        let state = self.tracker.state;
+        let arrow = match direction {
+            Direction::Serverbound => ">",
+            Direction::Clientbound => "<",
+        };
+        let mut input = Cursor::new(frame);
+        let packet = match inspect::decode_with_mode(
+            state,
+            direction,
+            &mut input,
+            TrailingBytesMode::Strict,
+        ) {
+            Ok(packet) => packet,
+            Err(e) => {
+                println!(
+                    "{} {arrow} {state:?} unknown {} ({e})",
+                    self.id,
+                    inspect::hex(input.get_ref())
+                );
+                return self.write(direction, input.get_ref()).await;
+            }
+        };
+        let rendered = match self.json {
+            true => serde_json::to_string(&packet)?,
+            false => format!("{packet:?}"),
+        };
+        println!("{} {arrow} {state:?} {rendered}", self.id);
+        self.tracker.observe(&packet);
+
+        match &packet {
+            Packet::ClientLogin(p) => {
+                if let Some(request) = p.as_any().downcast_ref::<EncryptionRequest>() {
+                    return self.encrypt(request).await;
+                }
+                if let Some(compression) = p.as_any().downcast_ref::<SetCompression>() {
+                    self.write(direction, input.get_ref()).await?;
+                    self.client.set_compression(compression.threshold.value);
+                    self.server.set_compression(compression.threshold.value);
+                    return Ok(());
+                }
+            }
+            Packet::Login(p) => {
+                if let Some(response) = p.as_any().downcast_ref::<EncryptionResponse>() {
+                    return self.accept_encryption(response);
+                }
+            }
+            _ => {}
+        }
+        return self.write(direction, input.get_ref()).await;
+    }
+
+    async fn write(&mut self, direction: Direction, frame: &[u8]) -> Result<()> {
+        return match direction {
+            Direction::Serverbound => self.server.write_frame(frame).await,
+            Direction::Clientbound => self.client.write_frame(frame).await,
+        };
+    }
+
+    /// Answers the server's encryption request, and asks the client for encryption in its place.
+    async fn encrypt(&mut self, request: &EncryptionRequest) -> Result<()> {
+        let (response, shared_secret) = client::answer_encryption_request(request)?;
+        self.server.send(&response, response.packet_id()).await?;
+        self.server.enable_encryption(&shared_secret)?;
+
+        let verify_token = rand::random::<[u8; 4]>().to_vec();
+        let request = EncryptionRequest {
+            server_id: request.server_id.clone(),
+            public_key: RsaPublicKey::from(&self.private_key)
+                .to_public_key_der()?
+                .as_ref()
+                .to_vec(),
+            verify_token: verify_token.clone(),
+        };
+        self.client.send(&request, request.packet_id()).await?;
+        self.verify_token = Some(verify_token);
+        return Ok(());
+    }
+
+    /// Encrypts the client's side with the secret from its encryption response.
+    fn accept_encryption(&mut self, response: &EncryptionResponse) -> Result<()> {
+        let verify_token = match self.verify_token.take() {
+            Some(token) => token,
+            None => bail!("the client answered an encryption request that was never sent"),
+        };
+        let decrypt = |bytes: &[u8]| {
+            return self
+                .private_key
+                .decrypt(PaddingScheme::new_pkcs1v15_encrypt(), bytes);
+        };
+        if decrypt(&response.verify_token)? != verify_token {
+            bail!("the client sent back the wrong verify token");
+        }
+        let shared_secret = decrypt(&response.shared_secret)?;
+        self.client.enable_encryption(&shared_secret)?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,98 @@
+//! Replays a capture recorded by the listener.
+//!
+//! ```text
+//! optical-replay decode <capture>
+//! optical-replay send <capture> <address> [--no-delay]
+//! ```
+//!
+//! `decode` prints every frame of the capture, decoded with the packet registries. `send` connects
+//! to a server and sends it the serverbound frames of the capture, waiting between frames as long
+//! as the client originally did, unless `--no-delay` is given.
+
+use std::{
+    env,
+    fs::File,
+    io::{self, BufReader, Cursor, Write},
+    net::{Shutdown, TcpStream},
+    process::ExitCode,
+    thread,
+    time::Duration,
+};
+
+use optical_protocol::{
+    capture::{CaptureReader, Frame},
+    inspect,
+    packets::Direction,
+};
+
+const USAGE: &str = "usage: optical-replay decode <capture>
+       optical-replay send <capture> <address> [--no-delay]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["decode", capture] => decode(capture),
+        ["send", capture, address] => send(capture, address, true),
+        ["send", capture, address, "--no-delay"] => send(capture, address, false),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("optical-replay: {e}");
+        return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
+}
+
+fn open(capture: &str) -> io::Result<CaptureReader<BufReader<File>>> {
+    return CaptureReader::new(BufReader::new(File::open(capture)?));
+}
+
+fn decode(capture: &str) -> io::Result<()> {
+    let mut start = None;
+    for frame in open(capture)? {
+        let frame = frame?;
+        let start = *start.get_or_insert(frame.timestamp);
+        let elapsed = frame.timestamp.saturating_sub(start);
+        let mut input = Cursor::new(frame.data.clone());
+        let packet = match inspect::decode(frame.state, frame.direction, &mut input) {
+            Ok(packet) => format!("{packet:?}"),
+            Err(e) => format!("{} ({e})", inspect::hex(&frame.data)),
+        };
+        println!(
+            "{:>12.3?} {:?} {:?} {}",
+            elapsed, frame.state, frame.direction, packet
+        );
+    }
+    return Ok(());
+}
+
+fn send(capture: &str, address: &str, delay: bool) -> io::Result<()> {
+    let frames: Vec<Frame> = open(capture)?
+        .filter(|frame| !matches!(frame, Ok(f) if f.direction == Direction::Clientbound))
+        .collect::<io::Result<_>>()?;
+    let mut stream = TcpStream::connect(address)?;
+
+    // Whatever the server answers is counted and dropped, so it never blocks on a full socket.
+    let mut reader = stream.try_clone()?;
+    let drain = thread::spawn(move || io::copy(&mut reader, &mut io::sink()));
+
+    let mut previous = None;
+    for frame in &frames {
+        if let (true, Some(previous)) = (delay, previous) {
+            thread::sleep(frame.timestamp.saturating_sub(previous));
+        }
+        previous = Some(frame.timestamp);
+        stream.write_all(&frame.data)?;
+    }
+    println!("Sent {} frames to {address}", frames.len());
+
+    // Give the server a moment to answer the last frames before hanging up.
+    thread::sleep(Duration::from_millis(500));
+    stream.shutdown(Shutdown::Write)?;
+    let received = drain.join().unwrap_or(Ok(0))?;
+    println!("Received {received} bytes");
+    return Ok(());
+}
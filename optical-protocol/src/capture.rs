@@ -0,0 +1,146 @@
+//! Capture files, which hold the raw frames of a single connection.
+//!
+//! A capture starts with [`MAGIC`] and [`VERSION`], followed by one record per frame:
+//!
+//! - the time the frame was seen, in microseconds since the unix epoch, as a big endian `u64`
+//! - the direction of the frame, `0` for serverbound and `1` for clientbound
+//! - the protocol state the frame was sent in, `0` to `3` for void, status, login and play
+//! - the length of the frame, as a big endian `u32`
+//! - the frame itself, packet length and id included
+//!
+//! Frames are stored exactly as they were sent, so they can be fed back into a server, or decoded
+//! later with [`inspect`](crate::inspect).
+
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::packets::{Direction, ProtocolState};
+
+/// The bytes every capture file starts with.
+pub const MAGIC: [u8; 4] = *b"OPCP";
+/// The version of the capture format, written after [`MAGIC`].
+pub const VERSION: u8 = 1;
+
+/// A single recorded frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The time since the unix epoch at which the frame was seen.
+    pub timestamp: Duration,
+    pub direction: Direction,
+    pub state: ProtocolState,
+    /// The frame, packet length and id included.
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates a frame seen right now.
+    pub fn now(direction: Direction, state: ProtocolState, data: Vec<u8>) -> Self {
+        return Frame {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            direction,
+            state,
+            data,
+        };
+    }
+
+    /// Appends the record of this frame to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let micros = u64::try_from(self.timestamp.as_micros()).unwrap_or(u64::MAX);
+        buf.extend(micros.to_be_bytes());
+        buf.push(match self.direction {
+            Direction::Serverbound => 0,
+            Direction::Clientbound => 1,
+        });
+        buf.push(match self.state {
+            ProtocolState::Void => 0,
+            ProtocolState::Status => 1,
+            ProtocolState::Login => 2,
+            ProtocolState::Play => 3,
+        });
+        buf.extend((self.data.len() as u32).to_be_bytes());
+        buf.extend(&self.data);
+    }
+}
+
+/// Writes the header of a capture file.
+pub fn write_header(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    return Ok(());
+}
+
+/// Reads the frames of a capture file, one at a time.
+pub struct CaptureReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Checks the header of a capture file, and returns a reader for its frames.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(invalid_data("not a capture file"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid_data("unsupported capture version"));
+        }
+        return Ok(CaptureReader { reader });
+    }
+
+    /// Reads the next frame. Returns None once the capture ends.
+    pub fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut header = [0u8; 14];
+        match self.reader.read_exact(&mut header[..1]) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        self.reader.read_exact(&mut header[1..])?;
+
+        let micros = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let direction = match header[8] {
+            0 => Direction::Serverbound,
+            1 => Direction::Clientbound,
+            _ => return Err(invalid_data("invalid frame direction")),
+        };
+        let state = match header[9] {
+            0 => ProtocolState::Void,
+            1 => ProtocolState::Status,
+            2 => ProtocolState::Login,
+            3 => ProtocolState::Play,
+            _ => return Err(invalid_data("invalid frame protocol state")),
+        };
+        let length = u32::from_be_bytes(header[10..].try_into().unwrap());
+        let mut data = vec![];
+        (&mut self.reader)
+            .take(length.into())
+            .read_to_end(&mut data)?;
+        if data.len() as u64 != u64::from(length) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        return Ok(Some(Frame {
+            timestamp: Duration::from_micros(micros),
+            direction,
+            state,
+            data,
+        }));
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.read_frame().transpose();
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, message);
+}
@@ -0,0 +1,288 @@
+//! An async client for Minecraft servers, for writing bots and for testing servers.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use optical_protocol::client::Client;
+//!
+//! let (status, latency) = Client::connect("localhost:25565").await?.status().await?;
+//! println!("{} players online, {latency:?} away", status.players.map_or(0, |p| p.online));
+//!
+//! let mut client = Client::connect("localhost:25565").await?;
+//! let profile = client.login("Bot").await?;
+//! while let Some(packet) = client.recv().await? {
+//!     println!("{} got {packet:?}", profile.username);
+//! }
+//! # return Ok(());
+//! # }
+//! ```
+//!
+//! Logins are offline: when the server asks for encryption, the connection is encrypted, but the
+//! client doesn't join a session with Mojang. Servers in online mode disconnect it.
+
+use std::{
+    any::Any,
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Result};
+use pkcs1::DecodeRsaPublicKey;
+use rsa::{pkcs8::DecodePublicKey, PaddingScheme, PublicKey, RsaPublicKey};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::net::TcpStream;
+
+use crate::{
+    format::{
+        deserializer,
+        error::Error,
+        tags::{
+            ClientLoginPacket, ClientPlayPacket, ClientStatusPacket, LoginPacket, PlayPacket,
+            StatusPacket, VoidPacket,
+        },
+        types::TrailingOption,
+    },
+    packets::{
+        clientbound,
+        login::{
+            clientbound::{
+                Disconnect, EncryptionRequest, LoginPluginRequest, LoginSuccess, SetCompression,
+            },
+            serverbound::{EncryptionResponse, LoginPluginResponse, LoginStart},
+        },
+        serverbound,
+        status::{
+            clientbound::{PingResponse, ServerStatus, StatusResponse},
+            serverbound::{PingRequest, StatusRequest},
+        },
+        void::serverbound::Handshake,
+        ProtocolState, PROTOCOL_VERSION,
+    },
+    stream::PacketStream,
+};
+
+/// The port servers listen on when the address doesn't name one.
+const DEFAULT_PORT: u16 = 25565;
+
+/// A connection to a server.
+pub struct Client {
+    stream: PacketStream,
+    host: String,
+    port: u16,
+    state: ProtocolState,
+}
+
+impl Client {
+    /// Connects to a server at `host:port`, where IPv6 addresses are written in brackets like
+    /// `[::1]:25565`. The port defaults to 25565.
+    pub async fn connect(address: &str) -> Result<Client> {
+        let (host, port) = split_address(address)?;
+        let socket = TcpStream::connect((host, port)).await?;
+        return Ok(Client {
+            stream: PacketStream::new(socket),
+            host: host.to_string(),
+            port,
+            state: ProtocolState::Void,
+        });
+    }
+
+    /// The protocol state the connection is in.
+    pub fn state(&self) -> ProtocolState {
+        return self.state;
+    }
+
+    /// Queries the server list information of the server, and measures the latency to it.
+    pub async fn status(mut self) -> Result<(ServerStatus, Duration)> {
+        self.handshake(ProtocolState::Status).await?;
+        let request = StatusRequest {};
+        self.stream.send(&request, request.packet_id()).await?;
+        let response: StatusResponse =
+            downcast(self.read::<dyn ClientStatusPacket>().await?.into_any())?;
+
+        let ping = PingRequest {
+            payload: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64,
+        };
+        let sent = Instant::now();
+        self.stream.send(&ping, ping.packet_id()).await?;
+        let pong: PingResponse = downcast(self.read::<dyn ClientStatusPacket>().await?.into_any())?;
+        let latency = sent.elapsed();
+        if pong.payload != ping.payload {
+            bail!("the server answered the ping with a different payload");
+        }
+        return Ok((response.status.0, latency));
+    }
+
+    /// Logs into the server, and moves the connection into the Play state.
+    pub async fn login(&mut self, username: &str) -> Result<LoginSuccess> {
+        self.handshake(ProtocolState::Login).await?;
+        let login_start = LoginStart {
+            name: username.to_string(),
+            player_uuid: TrailingOption(Some(None)),
+        };
+        self.stream
+            .send(&login_start, login_start.packet_id())
+            .await?;
+
+        loop {
+            let packet = self.read::<dyn ClientLoginPacket>().await?;
+            match packet.packet_id() {
+                0 => {
+                    let disconnect: Disconnect = downcast(packet.into_any())?;
+                    bail!("disconnected during login: {}", disconnect.reason);
+                }
+                1 => self.encrypt(downcast(packet.into_any())?).await?,
+                2 => {
+                    self.state = ProtocolState::Play;
+                    return downcast(packet.into_any());
+                }
+                3 => {
+                    let compression: SetCompression = downcast(packet.into_any())?;
+                    self.stream.set_compression(compression.threshold.value);
+                }
+                4 => {
+                    // Like vanilla, the client understands no login plugin channel.
+                    let request: LoginPluginRequest = downcast(packet.into_any())?;
+                    let response = LoginPluginResponse {
+                        message_id: request.message_id,
+                        data: None,
+                    };
+                    self.stream.send(&response, response.packet_id()).await?;
+                }
+                _ => bail!("the server sent an unsupported login packet: {packet:?}"),
+            }
+        }
+    }
+
+    /// Returns the next packet from the server once logged in. Packets without a definition are
+    /// skipped, and keep alives are answered before being returned, so the server doesn't time
+    /// out a client waiting here. Returns None if the server closed the connection.
+    pub async fn recv(&mut self) -> Result<Option<Box<dyn ClientPlayPacket>>> {
+        if self.state != ProtocolState::Play {
+            bail!("packets can only be received once logged in");
+        }
+        loop {
+            let mut frame = match self.stream.read_frame().await? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            match deserializer::from_bytes_generic::<Box<dyn ClientPlayPacket>>(&mut frame) {
+                Ok(packet) => {
+                    if let Some(keep_alive) =
+                        packet.as_any().downcast_ref::<clientbound::KeepAlive>()
+                    {
+                        let answer = serverbound::KeepAlive { id: keep_alive.id };
+                        self.stream.send(&answer, answer.packet_id()).await?;
+                    }
+                    return Ok(Some(packet));
+                }
+                Err(Error::UnknownVariant(id)) => {
+                    debug!(target: "client", "Skipped clientbound packet {}", id);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Sends a packet to the server once logged in.
+    pub async fn send<P: PlayPacket + Serialize>(&mut self, packet: &P) -> Result<()> {
+        if self.state != ProtocolState::Play {
+            bail!("packets can only be sent once logged in");
+        }
+        return self.stream.send(packet, packet.packet_id()).await;
+    }
+
+    async fn handshake(&mut self, next_state: ProtocolState) -> Result<()> {
+        if self.state != ProtocolState::Void {
+            bail!("the connection already left the handshake");
+        }
+        let handshake = Handshake {
+            protocol_version: PROTOCOL_VERSION.into(),
+            server_address: self.host.clone(),
+            server_port: self.port,
+            next_state: match next_state {
+                ProtocolState::Status => 1,
+                _ => 2,
+            }
+            .into(),
+        };
+        self.stream.send(&handshake, handshake.packet_id()).await?;
+        self.state = next_state;
+        return Ok(());
+    }
+
+    /// Answers an encryption request, and encrypts the connection from then on.
+    async fn encrypt(&mut self, request: EncryptionRequest) -> Result<()> {
+        let (response, shared_secret) = answer_encryption_request(&request)?;
+        self.stream.send(&response, response.packet_id()).await?;
+        self.stream.enable_encryption(&shared_secret)?;
+        return Ok(());
+    }
+
+    /// Reads the next packet of the current state.
+    async fn read<T: ?Sized>(&mut self) -> Result<Box<T>>
+    where
+        Box<T>: DeserializeOwned,
+    {
+        let mut frame = self
+            .stream
+            .read_frame()
+            .await?
+            .ok_or_else(|| anyhow!("the server closed the connection"))?;
+        return Ok(deserializer::from_bytes_generic(&mut frame)?);
+    }
+}
+
+/// Picks a shared secret, and encrypts it along with the verify token with the server's public
+/// key. Returns the response to send, and the secret to enable encryption with once it's sent.
+pub fn answer_encryption_request(
+    request: &EncryptionRequest,
+) -> Result<(EncryptionResponse, [u8; 16])> {
+    // Vanilla servers send a SubjectPublicKeyInfo, but a bare PKCS#1 key is accepted too.
+    let public_key = RsaPublicKey::from_public_key_der(&request.public_key)
+        .or_else(|_| RsaPublicKey::from_pkcs1_der(&request.public_key))
+        .map_err(|e| anyhow!("the server sent an invalid public key: {e}"))?;
+    let shared_secret = rand::random::<[u8; 16]>();
+
+    let mut rng = rand::thread_rng();
+    let response = EncryptionResponse {
+        shared_secret: public_key.encrypt(
+            &mut rng,
+            PaddingScheme::new_pkcs1v15_encrypt(),
+            &shared_secret,
+        )?,
+        verify_token: public_key.encrypt(
+            &mut rng,
+            PaddingScheme::new_pkcs1v15_encrypt(),
+            &request.verify_token,
+        )?,
+    };
+    return Ok((response, shared_secret));
+}
+
+/// Splits an address into its host, without the brackets of an IPv6 address, and its port.
+fn split_address(address: &str) -> Result<(&str, u16)> {
+    if address.parse::<IpAddr>().is_ok() {
+        // An IPv6 address without brackets, or an IPv4 address, without a port.
+        return Ok((address, DEFAULT_PORT));
+    }
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| anyhow!("unclosed bracket in {address}"))?;
+        return match rest.strip_prefix(':') {
+            Some(port) => Ok((host, port.parse()?)),
+            None if rest.is_empty() => Ok((host, DEFAULT_PORT)),
+            None => bail!("invalid address {address}"),
+        };
+    }
+    return match address.rsplit_once(':') {
+        Some((host, port)) => Ok((host, port.parse()?)),
+        None => Ok((address, DEFAULT_PORT)),
+    };
+}
+
+fn downcast<T: Any>(packet: Box<dyn Any>) -> Result<T> {
+    return packet
+        .downcast()
+        .map(|packet| *packet)
+        .map_err(|_| anyhow!("the server sent an unexpected packet"));
+}
@@ -0,0 +1,84 @@
+//! Helpers to use iterators as count-prefixed sequences, through `#[serde(with = "counted")]`.
+//!
+//! Packets sent to many clients are often built from an iterator, like a filtered list of entities.
+//! Instead of collecting the iterator into a `Vec` first, the iterator can be stored in the packet
+//! directly. Its elements are counted while serialized, and the count is written in front of them.
+//! On the receiving side, the sequence can be deserialized into any collection.
+//!
+//! ```
+//! use optical_protocol::format::counted;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize)]
+//! struct RemoveEntities<I: Iterator<Item = i32> + Clone> {
+//!     #[serde(serialize_with = "counted::serialize")]
+//!     entity_ids: I,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct ReceivedRemoveEntities {
+//!     #[serde(deserialize_with = "counted::deserialize")]
+//!     entity_ids: std::collections::VecDeque<i32>,
+//! }
+//! ```
+
+use core::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// Serializes every item of `iter` as a count-prefixed sequence. The iterator is cloned, so it
+/// should be cheap to clone, like most iterator adapters are.
+pub fn serialize<I, S>(iter: &I, serializer: S) -> Result<S::Ok, S::Error>
+where
+    I: IntoIterator + Clone,
+    I::Item: Serialize,
+    S: Serializer,
+{
+    return serializer.collect_seq(iter.clone());
+}
+
+/// Deserializes a count-prefixed sequence into any collection.
+pub fn deserialize<'de, C, D>(deserializer: D) -> Result<C, D::Error>
+where
+    C: IntoIterator + FromIterator<C::Item>,
+    C::Item: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    return deserializer.deserialize_seq(CountedVisitor(PhantomData));
+}
+
+struct CountedVisitor<C, T>(PhantomData<(C, T)>);
+
+impl<'de, C, T> Visitor<'de> for CountedVisitor<C, T>
+where
+    C: FromIterator<T>,
+    T: Deserialize<'de>,
+{
+    type Value = C;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a count-prefixed sequence")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut error = None;
+        let collection = core::iter::from_fn(|| match seq.next_element() {
+            Ok(element) => element,
+            Err(e) => {
+                error = Some(e);
+                None
+            }
+        })
+        .collect();
+        return match error {
+            Some(e) => Err(e),
+            None => Ok(collection),
+        };
+    }
+}
@@ -0,0 +1,60 @@
+//! The cursor packets are deserialized from.
+//!
+//! With the `std` feature this is [`std::io::Cursor`]. Without it, a minimal cursor with the same
+//! methods is used instead, so code written against one works with the other.
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+pub use std::io::Cursor;
+
+use super::error::Error;
+
+/// A buffer with a read position, a stand in for `std::io::Cursor`.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Cursor<T> {
+    inner: T,
+    position: u64,
+}
+
+#[cfg(not(feature = "std"))]
+impl<T> Cursor<T> {
+    pub fn new(inner: T) -> Self {
+        return Cursor { inner, position: 0 };
+    }
+
+    pub fn into_inner(self) -> T {
+        return self.inner;
+    }
+
+    pub fn get_ref(&self) -> &T {
+        return &self.inner;
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        return &mut self.inner;
+    }
+
+    pub fn position(&self) -> u64 {
+        return self.position;
+    }
+
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+}
+
+/// Returns the bytes after the position of the cursor.
+pub(crate) fn remaining(buf: &Cursor<Vec<u8>>) -> &[u8] {
+    let start = usize::try_from(buf.position()).unwrap_or(usize::MAX);
+    return buf.get_ref().get(start..).unwrap_or_default();
+}
+
+/// Fills `out` with the next bytes of the cursor, and moves the cursor past them.
+pub(crate) fn read_exact(buf: &mut Cursor<Vec<u8>>, out: &mut [u8]) -> Result<(), Error> {
+    let bytes = remaining(buf).get(..out.len()).ok_or(Error::NoMoreBytes)?;
+    out.copy_from_slice(bytes);
+    buf.set_position(buf.position() + out.len() as u64);
+    return Ok(());
+}
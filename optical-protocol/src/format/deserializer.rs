@@ -6,14 +6,21 @@
 //! packet id in order to identify the packet, while concrete packet types should already have a known packet id.
 //! Misusing these functions will result in indescribed behavior.
 
-use std::io::{Cursor, Read};
+use alloc::{string::ToString, vec::Vec};
+use core::marker::PhantomData;
 
+use super::cursor::{read_exact, remaining, Cursor};
 use super::error::Error;
 use super::types::{
-    read_string, read_var_int, read_var_long, Bytes, MinecraftUuid, VarInt, VarLong,
+    read_byte, read_string, read_var_int, read_var_long, variant_name, Bytes, Condition,
+    ConditionalOn, DiscriminantKind, Json, MinecraftUuid, OptionalVarInt, TrailingOption, VarInt,
+    VarLong, JSON_NAME, TRAILING_OPTION_NAME, VAR_INT_NAME, VAR_LONG_NAME,
 };
 use serde::de::Error as SerdeError;
-use serde::de::{DeserializeSeed, EnumAccess, VariantAccess, Visitor};
+use serde::de::{
+    value::StrDeserializer, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+    VariantAccess, Visitor,
+};
 use serde::{de::SeqAccess, Deserialize};
 
 pub struct Deserializer<'de> {
@@ -21,29 +28,71 @@ pub struct Deserializer<'de> {
     /// Is the next String element supposed to be deserialized
     /// as a var int?
     need_id_read: bool,
-    /// Is the next String element supposed to be deserialized
-    /// as a var int, and then mapped into a variant name?
-    need_id_variant: Option<&'static [&'static str]>,
+    /// Where the packet being deserialized ends, so trailing options and byte arrays don't read
+    /// into the next one.
+    end: u64,
 }
 
 impl<'de> Deserializer<'de> {
     pub fn from_bytes(input: &'de mut Cursor<Vec<u8>>, need_id_read: bool) -> Self {
+        let end = input.get_ref().len() as u64;
         return Deserializer {
             input,
             need_id_read,
-            need_id_variant: None,
+            end,
         };
     }
+
+    /// Ends the packet `length` bytes after the current position, or at the end of the input if
+    /// there are fewer bytes left.
+    fn with_length(mut self, length: i32) -> Result<Self, Error> {
+        let length: u64 = length.try_into().map_err(|_| Error::MalformedVarInt)?;
+        self.end = self.end.min(self.input.position() + length);
+        return Ok(self);
+    }
+
+    /// The bytes left in the packet.
+    fn rest_of_packet(&self) -> &[u8] {
+        let left = self.end.saturating_sub(self.input.position()) as usize;
+        let rest = remaining(self.input);
+        return &rest[..left.min(rest.len())];
+    }
+}
+
+/// How leftover bytes after a deserialized packet are handled.
+///
+/// A packet announces its own length. If the deserialized type doesn't consume exactly that many
+/// bytes, the type's field layout most likely doesn't match the packet sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingBytesMode {
+    /// Return an error if the consumed bytes don't match the announced packet length.
+    Strict,
+    /// Log a warning with the number of leftover bytes, and return the packet anyway.
+    #[default]
+    Lenient,
 }
 
 pub fn from_bytes<'a, T>(input: &'a mut Cursor<Vec<u8>>) -> Result<T, Error>
 where
     T: Deserialize<'a>,
 {
-    read_var_int(input).map_err(|_| Error::MalformedVarInt)?; // packet length
-    read_var_int(input).map_err(|_| Error::MalformedVarInt)?; // packet id
-    let mut deserializer = Deserializer::from_bytes(input, false);
+    return from_bytes_with_mode(input, TrailingBytesMode::default());
+}
+
+/// Like [`from_bytes`], but with a specific way to handle trailing bytes.
+pub fn from_bytes_with_mode<'a, T>(
+    input: &'a mut Cursor<Vec<u8>>,
+    mode: TrailingBytesMode,
+) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    let length = read_var_int(input).map_err(|_| Error::MalformedVarInt)?; // packet length
+    let start = input.position();
+    let mut deserializer = Deserializer::from_bytes(input, false).with_length(length.value)?;
+    read_var_int(deserializer.input).map_err(|_| Error::MalformedVarInt)?; // packet id
     let t = T::deserialize(&mut deserializer)?;
+    check_length(length.value, start, deserializer.input.position(), mode)?;
     return Ok(t);
 }
 
@@ -52,12 +101,80 @@ pub fn from_bytes_generic<'a, T>(input: &'a mut Cursor<Vec<u8>>) -> Result<T, Er
 where
     T: Deserialize<'a>,
 {
-    read_var_int(input).map_err(|_| Error::MalformedVarInt)?; // packet length
-    let mut deserializer = Deserializer::from_bytes(input, true);
+    return from_bytes_generic_with_mode(input, TrailingBytesMode::default());
+}
+
+/// Like [`from_bytes_generic`], but with a specific way to handle trailing bytes.
+pub fn from_bytes_generic_with_mode<'a, T>(
+    input: &'a mut Cursor<Vec<u8>>,
+    mode: TrailingBytesMode,
+) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    let length = read_var_int(input).map_err(|_| Error::MalformedVarInt)?; // packet length
+    let start = input.position();
+    let mut deserializer = Deserializer::from_bytes(input, true).with_length(length.value)?;
     let t = T::deserialize(&mut deserializer)?;
+    check_length(length.value, start, deserializer.input.position(), mode)?;
     return Ok(t);
 }
 
+/// Deserializes a value which isn't a packet, like the data of a plugin message, from all of
+/// `input`. Bytes left after the value are an error.
+pub fn from_payload<'a, T>(input: &'a mut Cursor<Vec<u8>>) -> Result<T, Error>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_bytes(input, false);
+    let t = T::deserialize(&mut deserializer)?;
+    let left = remaining(deserializer.input).len();
+    if left != 0 {
+        return Err(Error::TrailingBytes(left));
+    }
+    return Ok(t);
+}
+
+/// Compares the announced packet length with the amount of bytes consumed between `start` and
+/// `end`.
+fn check_length(
+    announced: i32,
+    start: u64,
+    end: u64,
+    mode: TrailingBytesMode,
+) -> Result<(), Error> {
+    let announced: usize = announced.try_into().map_err(|_| Error::MalformedVarInt)?;
+    let consumed = (end - start) as usize;
+    if consumed == announced {
+        return Ok(());
+    }
+
+    match mode {
+        TrailingBytesMode::Strict if consumed < announced => {
+            return Err(Error::TrailingBytes(announced - consumed));
+        }
+        TrailingBytesMode::Strict => {
+            return Err(Error::LengthMismatch {
+                announced,
+                consumed,
+            });
+        }
+        TrailingBytesMode::Lenient if consumed < announced => {
+            warn!(
+                "Packet had {} trailing bytes left after deserialization.",
+                announced - consumed
+            );
+        }
+        TrailingBytesMode::Lenient => {
+            warn!(
+                "Packet announced a length of {} bytes, but {} were consumed.",
+                announced, consumed
+            );
+        }
+    }
+    return Ok(());
+}
+
 impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
     type Error = Error;
 
@@ -73,9 +190,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut byte = [0u8];
-        self.input
-            .read_exact(&mut byte)
-            .map_err(|_| Error::MalformedBool)?;
+        read_exact(self.input, &mut byte).map_err(|_| Error::MalformedBool)?;
         return visitor.visit_bool(byte[0] == 1);
     }
 
@@ -84,9 +199,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 1];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedI8)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedI8)?;
         return visitor.visit_i8(i8::from_be_bytes(bytes));
     }
 
@@ -95,9 +208,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 2];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedI16)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedI16)?;
         return visitor.visit_i16(i16::from_be_bytes(bytes));
     }
 
@@ -106,9 +217,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 4];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedI32)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedI32)?;
         return visitor.visit_i32(i32::from_be_bytes(bytes));
     }
 
@@ -117,9 +226,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 8];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedI64)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedI64)?;
         return visitor.visit_i64(i64::from_be_bytes(bytes));
     }
 
@@ -128,7 +235,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut byte = [0u8];
-        match self.input.read_exact(&mut byte) {
+        match read_exact(self.input, &mut byte) {
             Ok(_) => visitor.visit_u8(byte[0]),
             Err(_) => return Err(Error::NoMoreBytes),
         }
@@ -139,9 +246,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 2];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedU16)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedU16)?;
         return visitor.visit_u16(u16::from_be_bytes(bytes));
     }
 
@@ -150,9 +255,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 4];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedU32)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedU32)?;
         return visitor.visit_u32(u32::from_be_bytes(bytes));
     }
 
@@ -161,9 +264,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 8];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedU64)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedU64)?;
         return visitor.visit_u64(u64::from_be_bytes(bytes));
     }
 
@@ -172,9 +273,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 4];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedF32)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedF32)?;
         return visitor.visit_f32(f32::from_be_bytes(bytes));
     }
 
@@ -183,9 +282,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut bytes = [0u8; 8];
-        self.input
-            .read_exact(&mut bytes)
-            .map_err(|_| Error::MalformedF64)?;
+        read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedF64)?;
         return visitor.visit_f64(f64::from_be_bytes(bytes));
     }
 
@@ -210,17 +307,6 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         if self.need_id_read == true {
             // Need to read some kind of var int id
             self.need_id_read = false;
-            if let Some(variants) = self.need_id_variant {
-                // Need to read some kind of var int
-                // id and map into an enum variant
-                self.need_id_variant = None;
-                let variant_index: usize = read_var_int(self.input)
-                    .map_err(|_| Error::MalformedVarInt)?
-                    .value
-                    .try_into()
-                    .map_err(|_| Error::MalformedVarInt)?;
-                return visitor.visit_string(variants[variant_index].to_string());
-            }
             let id = read_var_int(self.input)
                 .map_err(|_| Error::MalformedVarInt)?
                 .value;
@@ -241,11 +327,10 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut rest_of_input = vec![];
+        let rest_of_packet = self.rest_of_packet().to_vec();
         self.input
-            .read_to_end(&mut rest_of_input)
-            .map_err(|_| Error::NoMoreBytes)?;
-        return visitor.visit_byte_buf(rest_of_input);
+            .set_position(self.input.position() + rest_of_packet.len() as u64);
+        return visitor.visit_byte_buf(rest_of_packet);
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -253,9 +338,7 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: serde::de::Visitor<'de>,
     {
         let mut byte = [0u8];
-        self.input
-            .read_exact(&mut byte)
-            .map_err(|_| Error::MalformedBool)?;
+        read_exact(self.input, &mut byte).map_err(|_| Error::MalformedBool)?;
         let is_some = byte[0] == 1;
         if is_some {
             return visitor.visit_some(self);
@@ -284,12 +367,44 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
 
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        // Var ints and var longs are requested as newtype structs with a special name, read them
+        // directly instead of going through their inner integer.
+        if name == VAR_INT_NAME {
+            let value = read_var_int(self.input).map_err(|e| match e {
+                Error::NoMoreBytes => Error::MalformedVarInt,
+                e => e,
+            })?;
+            return visitor.visit_i32(value.value);
+        }
+        if name == VAR_LONG_NAME {
+            let value = read_var_long(self.input).map_err(|e| match e {
+                Error::NoMoreBytes => Error::MalformedVarLong,
+                e => e,
+            })?;
+            return visitor.visit_i64(value.value);
+        }
+        // Trailing options are present if the packet has bytes left.
+        if name == TRAILING_OPTION_NAME {
+            if !self.rest_of_packet().is_empty() {
+                return visitor.visit_some(self);
+            } else {
+                return visitor.visit_none();
+            }
+        }
+        // Json values are parsed from the JSON document in the next string.
+        if name == JSON_NAME {
+            let json = read_string(self.input).map_err(|_| Error::MalformedString)?;
+            // Parsed into a `Value` first, as the visitor needs a deserializer which doesn't borrow
+            // the string.
+            let document: serde_json::Value = serde_json::from_str(&json)?;
+            return Ok(visitor.visit_newtype_struct(document)?);
+        }
         return visitor.visit_newtype_struct(self);
     }
 
@@ -319,11 +434,11 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         return self.deserialize_tuple(len, visitor);
     }
 
-    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        return Err(Error::MapType);
+        return visitor.visit_map(Flatten::new(self, true)?);
     }
 
     fn deserialize_struct<V>(
@@ -340,19 +455,34 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
 
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        // The var int id which will be requested next will come in
-        // as a string. Communicate this to the parser to make sure
-        // it's parsed as a normal var int, and not a string.
-        self.need_id_read = true;
-        self.need_id_variant = Some(variants);
-        return visitor.visit_enum(Enum::new(self));
+        // The discriminant is read here and mapped into the name of its variant, which is then
+        // handed to the visitor instead of anything read from the input.
+        let discriminant: i64 = match DiscriminantKind::of(name) {
+            DiscriminantKind::Identifier => {
+                let identifier = read_string(self.input).map_err(|_| Error::MalformedString)?;
+                let variant = variants
+                    .iter()
+                    .find(|v| **v == identifier)
+                    .ok_or(Error::UnknownVariant(identifier))?;
+                return visitor.visit_enum(Enum::new(self, variant));
+            }
+            DiscriminantKind::U8 => read_byte(self.input)?.into(),
+            DiscriminantKind::I32 => {
+                let mut bytes = [0u8; 4];
+                read_exact(self.input, &mut bytes).map_err(|_| Error::MalformedI32)?;
+                i32::from_be_bytes(bytes).into()
+            }
+            DiscriminantKind::VarInt => read_var_int(self.input)?.value.into(),
+        };
+        let variant = variant_name(name, variants, discriminant)?;
+        return visitor.visit_enum(Enum::new(self, variant));
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -375,24 +505,25 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-/// All structs, maps, arrays are represented as sequences
-/// with no data for keys in the minecraft packet format. A
+/// All structs, arrays are represented as sequences
+/// with no data for keys in the minecraft packet format. Maps
+/// are sequences of keys, each followed by its value. A
 /// `Flatten` describes this behavior through its access
 /// implementations.
 struct Flatten<'a, 'de> {
     de: &'a mut Deserializer<'de>,
-    size: Option<VarInt>,
+    /// The amount of elements left to read, if the sequence is prefixed with its length.
+    remaining: Option<usize>,
 }
 
 impl<'a, 'de> Flatten<'a, 'de> {
     fn new(de: &'a mut Deserializer<'de>, read_length: bool) -> Result<Self, Error> {
-        let size;
+        let mut remaining = None;
         if read_length {
-            size = read_var_int(de.input).ok();
-        } else {
-            size = None;
+            let size = read_var_int(de.input).map_err(|_| Error::MalformedVarInt)?;
+            remaining = Some(size.value.try_into().map_err(|_| Error::MalformedVarInt)?);
         }
-        return Ok(Flatten { de, size });
+        return Ok(Flatten { de, remaining });
     }
 }
 
@@ -403,22 +534,52 @@ impl<'de, 'a> SeqAccess<'de> for Flatten<'a, 'de> {
     where
         T: serde::de::DeserializeSeed<'de>,
     {
+        if let Some(remaining) = self.remaining.as_mut() {
+            if *remaining == 0 {
+                return Ok(None);
+            }
+            *remaining -= 1;
+        }
         return seed.deserialize(&mut *self.de).map(Some);
     }
 
     fn size_hint(&self) -> Option<usize> {
-        // Return None if the size is negative somehow
-        return self.size.clone()?.value.try_into().ok();
+        return self.remaining;
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Flatten<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        // Maps always have a known length, which counts entries
+        return SeqAccess::next_element_seed(self, seed);
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        return seed.deserialize(&mut *self.de);
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        return self.remaining;
     }
 }
 
 struct Enum<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
+    /// The name of the variant, resolved from its discriminant.
+    variant: &'static str,
 }
 
 impl<'a, 'de> Enum<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>) -> Self {
-        return Enum { de };
+    fn new(de: &'a mut Deserializer<'de>, variant: &'static str) -> Self {
+        return Enum { de, variant };
     }
 }
 
@@ -430,8 +591,8 @@ impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
     where
         V: DeserializeSeed<'de>,
     {
-        println!("Yippee! Time to deserialize variant!");
-        let val = seed.deserialize(&mut *self.de)?;
+        let variant: StrDeserializer<Error> = self.variant.into_deserializer();
+        let val = seed.deserialize(variant)?;
         return Ok((val, self));
     }
 }
@@ -469,93 +630,97 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
     }
 }
 
-// Special deserialization logic for varints
+// Special deserialization logic for var ints and var longs. The optical deserializer reads them
+// directly when asked for a newtype struct with their special name, other formats just see the
+// inner integer.
+
+struct VarIntVisitor;
+
+impl<'de> Visitor<'de> for VarIntVisitor {
+    type Value = VarInt;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a var int")
+    }
+
+    fn visit_i32<E>(self, v: i32) -> Result<Self::Value, E>
+    where
+        E: SerdeError,
+    {
+        return Ok(VarInt::from(v));
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: SerdeError,
+    {
+        let v = i32::try_from(v).map_err(|_| E::custom("var int out of range"))?;
+        return self.visit_i32(v);
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: SerdeError,
+    {
+        let v = i32::try_from(v).map_err(|_| E::custom("var int out of range"))?;
+        return self.visit_i32(v);
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return Ok(VarInt::from(i32::deserialize(deserializer)?));
+    }
+}
 
 impl<'de> Deserialize<'de> for VarInt {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        struct VarIntVisitor;
+        return deserializer.deserialize_newtype_struct(VAR_INT_NAME, VarIntVisitor);
+    }
+}
 
-        impl<'de> Visitor<'de> for VarIntVisitor {
-            type Value = Result<VarInt, Error>;
+struct VarLongVisitor;
 
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a var int")
-            }
+impl<'de> Visitor<'de> for VarLongVisitor {
+    type Value = VarLong;
 
-            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-            where
-                A: serde::de::SeqAccess<'de>,
-            {
-                pub const CONTINUE_BIT: u8 = 0b10000000;
-                let mut buf = [0u8; 5];
-                let mut filled = 0;
-                loop {
-                    let next_byte: u8 = match seq.next_element()? {
-                        Some(n) => n,
-                        None => return Ok(Err(Error::MalformedVarInt)),
-                    };
-                    buf[filled] = next_byte;
-                    filled += 1;
-                    if next_byte & CONTINUE_BIT == 0 {
-                        break;
-                    }
-                }
-                return Ok(read_var_int(&mut Cursor::new(buf.to_vec()))
-                    .map_err(|_| Error::MalformedVarInt));
-            }
-        }
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a var long")
+    }
 
-        // The len is technically unknown, so just say its zero.
-        let res = deserializer.deserialize_tuple(0, VarIntVisitor)?;
-        return Ok(res.map_err(|e| SerdeError::custom(e))?);
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: SerdeError,
+    {
+        return Ok(VarLong::from(v));
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: SerdeError,
+    {
+        let v = i64::try_from(v).map_err(|_| E::custom("var long out of range"))?;
+        return self.visit_i64(v);
     }
-}
 
-// Special deserialization logic for var longs
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return Ok(VarLong::from(i64::deserialize(deserializer)?));
+    }
+}
 
 impl<'de> Deserialize<'de> for VarLong {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        struct VarLongVisitor;
-
-        impl<'de> Visitor<'de> for VarLongVisitor {
-            type Value = Result<VarLong, Error>;
-
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a var long")
-            }
-
-            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-            where
-                A: serde::de::SeqAccess<'de>,
-            {
-                pub const CONTINUE_BIT: u8 = 0b10000000;
-                let mut buf = [0u8; 10];
-                let mut filled = 0;
-                loop {
-                    let next_byte: u8 = match seq.next_element()? {
-                        Some(n) => n,
-                        None => return Ok(Err(Error::MalformedVarLong)),
-                    };
-                    buf[filled] = next_byte;
-                    filled += 1;
-                    if next_byte & CONTINUE_BIT == 0 {
-                        break;
-                    }
-                }
-                return Ok(read_var_long(&mut Cursor::new(buf.to_vec()))
-                    .map_err(|_| Error::MalformedVarLong));
-            }
-        }
-
-        // The len is technically unknown, so just say its zero.
-        let res = deserializer.deserialize_tuple(0, VarLongVisitor)?;
-        return Ok(res.map_err(|e| SerdeError::custom(e))?);
+        return deserializer.deserialize_newtype_struct(VAR_LONG_NAME, VarLongVisitor);
     }
 }
 
@@ -574,7 +739,7 @@ struct BytesVisitor;
 impl<'de> Visitor<'de> for BytesVisitor {
     type Value = Bytes;
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
         formatter.write_str("bytes")
     }
 
@@ -601,3 +766,127 @@ impl<'de> Deserialize<'de> for Bytes {
         return deserializer.deserialize_bytes(BytesVisitor);
     }
 }
+
+struct TrailingOptionVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for TrailingOptionVisitor<T> {
+    type Value = TrailingOption<T>;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a trailing option")
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: SerdeError,
+    {
+        return Ok(TrailingOption(None));
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return Ok(TrailingOption(Some(T::deserialize(deserializer)?)));
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return Ok(TrailingOption(Option::deserialize(deserializer)?));
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for TrailingOption<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return deserializer
+            .deserialize_newtype_struct(TRAILING_OPTION_NAME, TrailingOptionVisitor(PhantomData));
+    }
+}
+
+struct JsonVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for JsonVisitor<T> {
+    type Value = Json<T>;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a json document")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return Ok(Json(T::deserialize(deserializer)?));
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Json<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return deserializer.deserialize_newtype_struct(JSON_NAME, JsonVisitor(PhantomData));
+    }
+}
+
+impl<'de> Deserialize<'de> for OptionalVarInt {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = VarInt::deserialize(deserializer)?.value;
+        if value == 0 {
+            return Ok(OptionalVarInt(None));
+        }
+        return Ok(OptionalVarInt(Some(value)));
+    }
+}
+
+struct ConditionalOnVisitor<C, T>(PhantomData<(C, T)>);
+
+impl<'de, C, T> Visitor<'de> for ConditionalOnVisitor<C, T>
+where
+    C: Condition + Deserialize<'de>,
+    T: Deserialize<'de>,
+{
+    type Value = ConditionalOn<C, T>;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a condition, followed by a value if the condition is met")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let condition: C = seq
+            .next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let mut value = None;
+        if condition.is_present() {
+            value = Some(
+                seq.next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?,
+            );
+        }
+        return Ok(ConditionalOn { condition, value });
+    }
+}
+
+impl<'de, C, T> Deserialize<'de> for ConditionalOn<C, T>
+where
+    C: Condition + Deserialize<'de>,
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        return deserializer.deserialize_tuple(2, ConditionalOnVisitor(PhantomData));
+    }
+}
@@ -1,4 +1,5 @@
-use std::fmt::Display;
+use alloc::string::{String, ToString};
+use core::fmt::Display;
 
 use serde::{de, ser};
 use thiserror::Error;
@@ -11,8 +12,6 @@ pub enum Error {
     // Deserialization/serialization errors
     #[error("'any' types do not exist in this format")]
     AnyType,
-    #[error("map types do not exist in this format")]
-    MapType,
     #[error("char types do not exist in this format")]
     CharType,
     #[error("failed parsing a var int")]
@@ -43,10 +42,22 @@ pub enum Error {
     MalformedF64,
     #[error("failed parsing a boolean")]
     MalformedBool,
-
-    // Serialization errors
-    #[error("attempted serializing a sequence with no known length")]
-    UnsizedSeq,
+    #[error("failed converting json: {0}")]
+    MalformedJson(#[from] serde_json::Error),
+    #[error("enum variant with discriminant {0} does not exist")]
+    UnknownDiscriminant(i64),
+    #[error("enum variant named {0} does not exist")]
+    UnknownVariant(String),
+    #[error("enum discriminant {0} doesn't fit in its type")]
+    DiscriminantOutOfRange(i64),
+    #[error("enum marker {0} doesn't list a valid id for every variant")]
+    InvalidEnumMarker(&'static str),
+    #[error("packet length of {0} bytes is invalid")]
+    InvalidPacketLength(i32),
+    #[error("packet had {0} trailing bytes left after deserialization")]
+    TrailingBytes(usize),
+    #[error("packet announced a length of {announced} bytes, but {consumed} were consumed")]
+    LengthMismatch { announced: usize, consumed: usize },
 }
 
 impl ser::Error for Error {
@@ -59,4 +70,9 @@ impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error::Message(msg.to_string())
     }
+
+    // Packet registries report packet ids without a definition through this.
+    fn unknown_variant(variant: &str, _expected: &'static [&'static str]) -> Self {
+        Error::UnknownVariant(variant.to_string())
+    }
 }
@@ -0,0 +1,57 @@
+//! Splitting the frames of a connection apart.
+
+use std::io::Cursor;
+
+use super::{
+    error::Error,
+    types::{read_var_int, MAX_PACKET_LENGTH},
+};
+
+/// Splits a complete packet, including its length, off the front of `buf`. Returns None if `buf`
+/// doesn't hold an entire packet yet.
+pub fn split_packet(buf: &mut Vec<u8>) -> Result<Option<Cursor<Vec<u8>>>, Error> {
+    // Attempt reading a packet length
+    let mut reader = Cursor::new(std::mem::take(buf));
+    let length = read_var_int(&mut reader);
+    *buf = reader.into_inner();
+    let length = match length {
+        Ok(n) => n,
+        // Not enough data
+        Err(Error::NoMoreBytes) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    // A packet always holds at least its id
+    if length.value <= 0 || length.value > MAX_PACKET_LENGTH {
+        return Err(Error::InvalidPacketLength(length.value));
+    }
+
+    // Check if the buffer has enough to pop packet
+    let length_entire_packet = length.value as usize + length.size;
+    if length_entire_packet > buf.len() {
+        return Ok(None);
+    }
+
+    // Split the buffer
+    let remaining_buf = buf.split_off(length_entire_packet);
+    // Get the packet
+    let packet = std::mem::replace(buf, remaining_buf);
+
+    return Ok(Some(Cursor::new(packet)));
+}
+
+/// Checks every frame in `frames`, as they're about to be sent, is no longer than
+/// [`MAX_PACKET_LENGTH`], which is all a peer reads.
+pub fn check_frames(mut frames: &[u8]) -> Result<(), Error> {
+    while !frames.is_empty() {
+        // A var int is at most 5 bytes long, so only those are copied.
+        let prefix = frames[..frames.len().min(5)].to_vec();
+        let length = read_var_int(&mut Cursor::new(prefix))?;
+        if length.value <= 0 || length.value > MAX_PACKET_LENGTH {
+            return Err(Error::InvalidPacketLength(length.value));
+        }
+        let end = (length.size + length.value as usize).min(frames.len());
+        frames = &frames[end..];
+    }
+    return Ok(());
+}
@@ -1,7 +1,12 @@
 //! Deserializers and Serializers for the Minecraft protocol format.
 
+pub mod counted;
+pub mod cursor;
 pub mod deserializer;
 pub mod error;
+#[cfg(feature = "std")]
+pub mod framing;
 pub mod serializer;
+#[cfg(feature = "std")]
 pub mod tags;
 pub mod types;
@@ -1,116 +1,229 @@
 //! Serializer for the Minecraft protocol format.
 
+use alloc::vec::Vec;
+
 use super::{
     error::Error,
-    types::{write_string, write_var_int, write_var_long, Bytes, MinecraftUuid, VarInt, VarLong},
+    types::{
+        variant_discriminant, write_string, write_var_int, write_var_long, Bytes, Condition,
+        ConditionalOn, DiscriminantKind, Json, MinecraftUuid, OptionalVarInt, TrailingOption,
+        VarInt, VarLong, JSON_NAME, MAX_UNCOMPRESSED_LENGTH, TRAILING_OPTION_NAME, VAR_INT_NAME,
+        VAR_LONG_NAME,
+    },
 };
+use bytes::{BufMut, BytesMut};
 use serde::{
-    ser::{self, Error as SerdeError},
+    ser::{self, Error as SerdeError, SerializeTuple},
     Serialize,
 };
 
-pub struct Serializer {
-    output: Vec<u8>,
+pub struct Serializer<'a> {
+    output: &'a mut BytesMut,
+    /// Is the next integer supposed to be serialized as a var int/var long?
+    need_var_num: bool,
+    /// Is the next option supposed to be serialized without its boolean prefix?
+    omit_option_prefix: bool,
 }
 
+/// The amount of bytes reserved for the length of a packet, enough for a var int holding
+/// [`MAX_UNCOMPRESSED_LENGTH`].
+const LENGTH_PREFIX_SIZE: usize = 4;
+
 pub fn to_bytes<T>(value: &T, packet_id: i32) -> Result<Vec<u8>, Error>
 where
     T: Serialize,
 {
-    let mut serializer = Serializer { output: Vec::new() };
+    let mut buf = BytesMut::new();
+    encode_into(value, packet_id, &mut buf)?;
+    return Ok(buf.into());
+}
+
+/// Serializes a value which isn't a packet, like the data of a plugin message, without a length
+/// or id in front.
+pub fn to_payload<T>(value: &T) -> Result<Vec<u8>, Error>
+where
+    T: Serialize,
+{
+    let mut buf = BytesMut::new();
+    let mut serializer = Serializer {
+        output: &mut buf,
+        need_var_num: false,
+        omit_option_prefix: false,
+    };
     value.serialize(&mut serializer)?;
-    // Temp vec to store packet id
-    let mut packet_id_buf = vec![];
-    write_size_or_index(&mut packet_id_buf, packet_id)?;
-    // Temp vec to store packet length
-    let mut packet_len_buf = vec![];
-    write_size_or_index(
-        &mut packet_len_buf,
-        packet_id_buf.len() + serializer.output.len(),
-    )?;
-
-    return Ok([packet_len_buf, packet_id_buf, serializer.output].concat());
+    return Ok(buf.into());
+}
+
+/// Serializes a packet, including its length and id, onto the end of `buf`.
+///
+/// The packet is written in place, right after space reserved for its length. Calling this for
+/// multiple packets with the same buffer batches them, so they can be sent with a single write.
+///
+/// Packets may be up to [`MAX_UNCOMPRESSED_LENGTH`] long here, as compression may shrink them.
+/// Whatever writes the frames to a connection checks they fit in
+/// [`MAX_PACKET_LENGTH`](super::types::MAX_PACKET_LENGTH) once they're compressed.
+pub fn encode_into<T>(value: &T, packet_id: i32, buf: &mut BytesMut) -> Result<(), Error>
+where
+    T: Serialize,
+{
+    let start = buf.len();
+    buf.put_bytes(0, LENGTH_PREFIX_SIZE);
+    write_size_or_index(buf, packet_id)?;
+
+    let mut serializer = Serializer {
+        output: buf,
+        need_var_num: false,
+        omit_option_prefix: false,
+    };
+    if let Err(e) = value.serialize(&mut serializer) {
+        // Leave the buffer as it was, so previously batched packets stay intact
+        buf.truncate(start);
+        return Err(e);
+    }
+
+    // Write the length right in front of the packet, and close the gap left in the reserved
+    // space if the length took up less of it.
+    let body = start + LENGTH_PREFIX_SIZE;
+    let length = buf.len() - body;
+    if length > MAX_UNCOMPRESSED_LENGTH {
+        buf.truncate(start);
+        return Err(Error::InvalidPacketLength(
+            length.try_into().unwrap_or(i32::MAX),
+        ));
+    }
+    let length_start = body - VarInt::encoded_size(length as i32);
+    write_var_int(&mut &mut buf[length_start..body], length as i32);
+    if length_start != start {
+        let gap = length_start - start;
+        buf.copy_within(length_start.., start);
+        buf.truncate(buf.len() - gap);
+    }
+    return Ok(());
 }
 
-fn write_size_or_index<T>(mut buf: &mut Vec<u8>, value: T) -> Result<(), Error>
+fn write_size_or_index<T>(buf: &mut impl BufMut, value: T) -> Result<(), Error>
 where
     T: TryInto<i32>,
-    <T as TryInto<i32>>::Error: std::fmt::Debug,
 {
-    // Safe to unwrap, very unlikely an enum variant or a size/index would
-    // be higher than an i32's max value.
-    write_var_int(&mut buf, value.try_into().unwrap())
-        .map_err(|e| Error::Message(e.to_string()))?;
+    // Very unlikely a size/index would be higher than an i32's max value, but
+    // a var int can't hold it if it is.
+    let value = value.try_into().map_err(|_| Error::MalformedVarInt)?;
+    write_var_int(buf, value);
     return Ok(());
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl<'a> Serializer<'a> {
+    /// Writes the discriminant of a variant, in the type picked by the name of its enum.
+    fn write_discriminant(
+        &mut self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        let kind = DiscriminantKind::of(name);
+        if kind == DiscriminantKind::Identifier {
+            write_string(self.output, variant)?;
+            return Ok(());
+        }
+        let discriminant = variant_discriminant(name, variant_index)?;
+        let out_of_range = Error::DiscriminantOutOfRange(discriminant);
+        match kind {
+            DiscriminantKind::U8 => {
+                self.output
+                    .put_u8(discriminant.try_into().map_err(|_| out_of_range)?);
+            }
+            DiscriminantKind::I32 => {
+                self.output
+                    .put_i32(discriminant.try_into().map_err(|_| out_of_range)?);
+            }
+            _ => {
+                write_var_int(
+                    self.output,
+                    discriminant.try_into().map_err(|_| out_of_range)?,
+                );
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     type Ok = ();
     type Error = Error;
-    type SerializeSeq = Self;
+    type SerializeSeq = Seq<'a, 'b>;
     type SerializeTuple = Self;
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
+    type SerializeMap = Seq<'a, 'b>;
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
         if v {
-            self.output.push(1);
+            self.output.put_u8(1);
         } else {
-            self.output.push(0);
+            self.output.put_u8(0);
         }
         return Ok(());
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        if self.need_var_num {
+            self.need_var_num = false;
+            write_var_int(self.output, v);
+            return Ok(());
+        }
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        if self.need_var_num {
+            self.need_var_num = false;
+            write_var_long(self.output, v);
+            return Ok(());
+        }
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        self.output.push(v);
+        self.output.put_u8(v);
         return Ok(());
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v.to_be_bytes());
+        self.output.extend_from_slice(&v.to_be_bytes());
         return Ok(());
     }
 
@@ -119,16 +232,20 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        write_string(&mut self.output, v).map_err(|_| Error::MalformedString)?;
+        write_string(self.output, v)?;
         return Ok(());
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        self.output.extend(v);
+        self.output.extend_from_slice(v);
         return Ok(());
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        if self.omit_option_prefix {
+            self.omit_option_prefix = false;
+            return Ok(());
+        }
         return self.serialize_bool(false);
     }
 
@@ -136,6 +253,10 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: Serialize,
     {
+        if self.omit_option_prefix {
+            self.omit_option_prefix = false;
+            return value.serialize(self);
+        }
         self.serialize_bool(true)?;
         return value.serialize(self);
     }
@@ -150,45 +271,68 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     fn serialize_unit_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        write_size_or_index(&mut self.output, variant_index)?;
+        self.write_discriminant(name, variant_index, variant)?;
         return Ok(());
     }
 
     fn serialize_newtype_struct<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
+        // Var ints and var longs wrap their value in a newtype struct with a special name, so the
+        // next integer is written with a variable length.
+        if name == VAR_INT_NAME || name == VAR_LONG_NAME {
+            self.need_var_num = true;
+        }
+        // Trailing options wrap an option which is written without its boolean prefix.
+        if name == TRAILING_OPTION_NAME {
+            self.omit_option_prefix = true;
+        }
+        // Json values are written as a string holding their JSON document.
+        if name == JSON_NAME {
+            let json = serde_json::to_string(value)?;
+            write_string(self.output, &json)?;
+            return Ok(());
+        }
         return value.serialize(self);
     }
 
     fn serialize_newtype_variant<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize,
     {
-        write_size_or_index(&mut self.output, variant_index)?;
+        self.write_discriminant(name, variant_index, variant)?;
         return value.serialize(self);
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         if let Some(value) = len {
-            write_size_or_index(&mut self.output, value)?;
-            return Ok(self);
+            write_size_or_index(self.output, value)?;
+            return Ok(Seq {
+                ser: self,
+                unsized_seq: None,
+            });
         } else {
-            return Err(Error::UnsizedSeq);
+            // The length is written in front of the elements once they're all serialized
+            let start = self.output.len();
+            return Ok(Seq {
+                ser: self,
+                unsized_seq: Some((start, 0)),
+            });
         }
     }
 
@@ -206,17 +350,18 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     fn serialize_tuple_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        write_size_or_index(&mut self.output, variant_index)?;
+        self.write_discriminant(name, variant_index, variant)?;
         return Ok(self);
     }
 
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        return Err(Error::MapType);
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        // Maps are sequences of key value pairs
+        return self.serialize_seq(len);
     }
 
     fn serialize_struct(
@@ -229,12 +374,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     fn serialize_struct_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        write_size_or_index(&mut self.output, variant_index)?;
+        self.write_discriminant(name, variant_index, variant)?;
         return Ok(self);
     }
 }
@@ -244,9 +389,7 @@ impl Serialize for VarInt {
     where
         S: serde::Serializer,
     {
-        let mut buf = vec![];
-        write_var_int(&mut buf, self.value).map_err(|e| SerdeError::custom(e.to_string()))?;
-        return serializer.serialize_bytes(&buf);
+        return serializer.serialize_newtype_struct(VAR_INT_NAME, &self.value);
     }
 }
 
@@ -255,9 +398,7 @@ impl Serialize for VarLong {
     where
         S: serde::Serializer,
     {
-        let mut buf = vec![];
-        write_var_long(&mut buf, self.value).map_err(|e| SerdeError::custom(e.to_string()))?;
-        return serializer.serialize_bytes(&buf);
+        return serializer.serialize_newtype_struct(VAR_LONG_NAME, &self.value);
     }
 }
 
@@ -271,7 +412,16 @@ impl Serialize for MinecraftUuid {
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+/// Serializes the elements of a sequence, or the entries of a map. Sequences and maps with no
+/// known length have their length inserted in front of their elements when they end.
+pub struct Seq<'a, 'b> {
+    ser: &'a mut Serializer<'b>,
+    /// Where the elements of a sequence with no known length start in the output, and how many
+    /// elements (or map entries) have been serialized so far.
+    unsized_seq: Option<(usize, usize)>,
+}
+
+impl<'a, 'b> ser::SerializeSeq for Seq<'a, 'b> {
     type Ok = ();
     type Error = Error;
 
@@ -279,15 +429,28 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     where
         T: Serialize,
     {
-        return value.serialize(&mut **self);
+        if let Some((_, count)) = self.unsized_seq.as_mut() {
+            *count += 1;
+        }
+        return value.serialize(&mut *self.ser);
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        if let Some((start, count)) = self.unsized_seq {
+            // Make room for the length in front of the elements, and write it there
+            let output = &mut *self.ser.output;
+            let count: i32 = count.try_into().map_err(|_| Error::MalformedVarInt)?;
+            let size = VarInt::encoded_size(count);
+            let end = output.len();
+            output.put_bytes(0, size);
+            output.copy_within(start..end, start + size);
+            write_var_int(&mut &mut output[start..start + size], count);
+        }
         return Ok(());
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl<'a, 'b> ser::SerializeTuple for &'a mut Serializer<'b> {
     type Ok = ();
     type Error = Error;
 
@@ -303,7 +466,7 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl<'a, 'b> ser::SerializeTupleStruct for &'a mut Serializer<'b> {
     type Ok = ();
     type Error = Error;
 
@@ -319,7 +482,7 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl<'a, 'b> ser::SerializeTupleVariant for &'a mut Serializer<'b> {
     type Ok = ();
     type Error = Error;
 
@@ -335,31 +498,31 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
-// This implementation will never be used.
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+impl<'a, 'b> ser::SerializeMap for Seq<'a, 'b> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, _key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
     where
         T: Serialize,
     {
-        todo!()
+        // Each key starts a new entry
+        return ser::SerializeSeq::serialize_element(self, key);
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, _value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
     where
         T: Serialize,
     {
-        todo!()
+        return value.serialize(&mut *self.ser);
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        todo!()
+        return ser::SerializeSeq::end(self);
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl<'a, 'b> ser::SerializeStruct for &'a mut Serializer<'b> {
     type Ok = ();
     type Error = Error;
 
@@ -379,7 +542,7 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+impl<'a, 'b> ser::SerializeStructVariant for &'a mut Serializer<'b> {
     type Ok = ();
     type Error = Error;
 
@@ -407,3 +570,57 @@ impl Serialize for Bytes {
         return serializer.serialize_bytes(&self.0);
     }
 }
+
+impl<T: Serialize> Serialize for TrailingOption<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        return serializer.serialize_newtype_struct(TRAILING_OPTION_NAME, &self.0);
+    }
+}
+
+impl<T: Serialize> Serialize for Json<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        return serializer.serialize_newtype_struct(JSON_NAME, &self.0);
+    }
+}
+
+impl Serialize for OptionalVarInt {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        return match self.0 {
+            None => VarInt::from(0).serialize(serializer),
+            Some(0) => Err(S::Error::custom("an optional var int can't hold a 0")),
+            Some(value) => VarInt::from(value).serialize(serializer),
+        };
+    }
+}
+
+impl<C, T> Serialize for ConditionalOn<C, T>
+where
+    C: Condition + Serialize,
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if self.condition.is_present() != self.value.is_some() {
+            return Err(S::Error::custom(
+                "the presence of a conditional value doesn't match its condition",
+            ));
+        }
+        let mut tuple = serializer.serialize_tuple(1 + self.value.is_some() as usize)?;
+        tuple.serialize_element(&self.condition)?;
+        if let Some(value) = &self.value {
+            tuple.serialize_element(value)?;
+        }
+        return tuple.end();
+    }
+}
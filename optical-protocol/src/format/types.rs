@@ -1,96 +1,394 @@
 //! Common types used in the Minecraft protocol format.
 
-use std::{
-    fmt::Debug,
-    io::{Cursor, Read},
+use alloc::{string::String, vec::Vec};
+use core::fmt::Debug;
+
+use bytes::BufMut;
+
+use super::{
+    cursor::{remaining, Cursor},
+    error::Error,
 };
 
-use anyhow::Result;
-use wasabi_leb128::{ReadLeb128, WriteLeb128};
+/// The maximum length of a packet, which is the largest number a 3 byte var int can hold.
+pub const MAX_PACKET_LENGTH: i32 = 2097151;
+
+/// The longest a packet may be before it's compressed, and a compressed frame may inflate to, as
+/// in vanilla.
+pub const MAX_UNCOMPRESSED_LENGTH: usize = 8388608;
+
+/// The bits of a var int/var long byte which hold data.
+const SEGMENT_BITS: u8 = 0b01111111;
+/// The bit of a var int/var long byte which signals another byte follows.
+const CONTINUE_BIT: u8 = 0b10000000;
+
+/// The name given to [`VarInt`] when serialized as a newtype struct. The optical serializer and
+/// deserializer look for this name to encode the inner `i32` as a var int.
+pub(crate) const VAR_INT_NAME: &str = "$optical::VarInt";
+/// The name given to [`VarLong`] when serialized as a newtype struct. The optical serializer and
+/// deserializer look for this name to encode the inner `i64` as a var long.
+pub(crate) const VAR_LONG_NAME: &str = "$optical::VarLong";
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct VarInt {
     pub value: i32,
+    /// The amount of bytes this var int takes up when encoded.
     pub size: usize,
 }
 
+impl VarInt {
+    /// The maximum amount of bytes a var int can be encoded as.
+    pub const MAX_SIZE: usize = 5;
+
+    /// Returns the amount of bytes `value` takes up when encoded as a var int.
+    pub fn encoded_size(value: i32) -> usize {
+        let bits = 32 - (value as u32).leading_zeros() as usize;
+        return bits.max(1).div_ceil(7);
+    }
+}
+
 impl From<i32> for VarInt {
     fn from(value: i32) -> Self {
         return VarInt {
             value,
-            // TODO: Make this accurate, but quickly generatyed
-            size: wasabi_leb128::max_bytes::<i32>(),
+            size: VarInt::encoded_size(value),
         };
     }
 }
 
-pub fn read_var_int(buf: &mut Cursor<Vec<u8>>) -> Result<VarInt> {
-    let res = buf.read_leb128()?;
-    return Ok(VarInt {
-        value: res.0,
-        size: res.1,
-    });
+/// Reads a single byte from the cursor, without going through `Read`.
+pub(crate) fn read_byte(buf: &mut Cursor<Vec<u8>>) -> Result<u8, Error> {
+    let position = buf.position();
+    let byte = *usize::try_from(position)
+        .ok()
+        .and_then(|i| buf.get_ref().get(i))
+        .ok_or(Error::NoMoreBytes)?;
+    buf.set_position(position + 1);
+    return Ok(byte);
 }
 
-pub fn write_var_int(buf: &mut Vec<u8>, value: i32) -> Result<usize> {
-    return Ok(buf.write_leb128(value)?);
+/// Reads a var int. Returns [`Error::NoMoreBytes`] if the buffer ends before the var int does,
+/// and [`Error::MalformedVarInt`] if the var int is longer than [`VarInt::MAX_SIZE`] bytes.
+pub fn read_var_int(buf: &mut Cursor<Vec<u8>>) -> Result<VarInt, Error> {
+    let mut value: u32 = 0;
+    for i in 0..VarInt::MAX_SIZE {
+        let byte = read_byte(buf)?;
+        value |= ((byte & SEGMENT_BITS) as u32) << (7 * i);
+        if byte & CONTINUE_BIT == 0 {
+            return Ok(VarInt {
+                value: value as i32,
+                size: i + 1,
+            });
+        }
+    }
+    return Err(Error::MalformedVarInt);
 }
 
-#[derive(Default, Debug, Clone)]
+/// Writes a var int, returning the amount of bytes written.
+pub fn write_var_int(buf: &mut impl BufMut, value: i32) -> usize {
+    let mut value = value as u32;
+    let mut written = 0;
+    loop {
+        written += 1;
+        if value & !(SEGMENT_BITS as u32) == 0 {
+            buf.put_u8(value as u8);
+            return written;
+        }
+        buf.put_u8((value as u8 & SEGMENT_BITS) | CONTINUE_BIT);
+        value >>= 7;
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct VarLong {
     pub value: i64,
+    /// The amount of bytes this var long takes up when encoded.
     pub size: usize,
 }
 
+impl VarLong {
+    /// The maximum amount of bytes a var long can be encoded as.
+    pub const MAX_SIZE: usize = 10;
+
+    /// Returns the amount of bytes `value` takes up when encoded as a var long.
+    pub fn encoded_size(value: i64) -> usize {
+        let bits = 64 - (value as u64).leading_zeros() as usize;
+        return bits.max(1).div_ceil(7);
+    }
+}
+
 impl From<i64> for VarLong {
     fn from(value: i64) -> Self {
         return VarLong {
             value,
-            // TODO: Make this accurate, but quickly generatyed
-            size: wasabi_leb128::max_bytes::<i64>(),
+            size: VarLong::encoded_size(value),
         };
     }
 }
 
-pub fn read_var_long(buf: &mut Cursor<Vec<u8>>) -> Result<VarLong> {
-    let res = buf.read_leb128()?;
-    return Ok(VarLong {
-        value: res.0,
-        size: res.1,
-    });
+/// Reads a var long. Returns [`Error::NoMoreBytes`] if the buffer ends before the var long does,
+/// and [`Error::MalformedVarLong`] if the var long is longer than [`VarLong::MAX_SIZE`] bytes.
+pub fn read_var_long(buf: &mut Cursor<Vec<u8>>) -> Result<VarLong, Error> {
+    let mut value: u64 = 0;
+    for i in 0..VarLong::MAX_SIZE {
+        let byte = read_byte(buf)?;
+        value |= ((byte & SEGMENT_BITS) as u64) << (7 * i);
+        if byte & CONTINUE_BIT == 0 {
+            return Ok(VarLong {
+                value: value as i64,
+                size: i + 1,
+            });
+        }
+    }
+    return Err(Error::MalformedVarLong);
 }
 
-pub fn write_var_long(buf: &mut Vec<u8>, value: i64) -> Result<usize> {
-    return Ok(buf.write_leb128(value)?);
+/// Writes a var long, returning the amount of bytes written.
+pub fn write_var_long(buf: &mut impl BufMut, value: i64) -> usize {
+    let mut value = value as u64;
+    let mut written = 0;
+    loop {
+        written += 1;
+        if value & !(SEGMENT_BITS as u64) == 0 {
+            buf.put_u8(value as u8);
+            return written;
+        }
+        buf.put_u8((value as u8 & SEGMENT_BITS) | CONTINUE_BIT);
+        value >>= 7;
+    }
 }
 
-pub fn read_string(buf: &mut Cursor<Vec<u8>>) -> Result<String> {
-    let len = read_var_int(buf)?;
-    let mut res = String::with_capacity(len.value.try_into()?);
-
-    for _ in 0..len.value {
-        let mut byte = [0u8];
-        buf.read_exact(&mut byte)?;
-        res.push(byte[0].into());
-    }
+pub fn read_string(buf: &mut Cursor<Vec<u8>>) -> Result<String, Error> {
+    let len: usize = read_var_int(buf)?
+        .value
+        .try_into()
+        .map_err(|_| Error::MalformedString)?;
+    let bytes = remaining(buf)
+        .get(..len)
+        .ok_or(Error::MalformedString)?
+        .to_vec();
+    buf.set_position(buf.position() + len as u64);
 
-    return Ok(res);
+    return String::from_utf8(bytes).map_err(|_| Error::MalformedString);
 }
 
-pub fn write_string(buf: &mut Vec<u8>, string_to_pack: impl Into<String>) -> Result<()> {
-    let string: String = string_to_pack.into();
-    write_var_int(buf, string.len().try_into()?)?;
-    buf.append(&mut string.into_bytes());
+pub fn write_string(buf: &mut impl BufMut, string: &str) -> Result<(), Error> {
+    write_var_int(
+        buf,
+        string
+            .len()
+            .try_into()
+            .map_err(|_| Error::MalformedString)?,
+    );
+    buf.put_slice(string.as_bytes());
 
     return Ok(());
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MinecraftUuid(pub uuid::Uuid);
 
 /// A wrapper around a `Vec<u8>`, represents some bytes.
 ///
 /// This type was created to be deserialized and serialized as a Serde byte buffer. A `&[u8]` can achieve
 /// this, but having a `&[u8]` requires a generic lifetime parameter, which `typetag` doesn't support.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Bytes(pub Vec<u8>);
+
+/// The name given to [`TrailingOption`] when serialized as a newtype struct. The optical
+/// serializer and deserializer look for this name to leave out the boolean prefix of the inner
+/// `Option`.
+pub(crate) const TRAILING_OPTION_NAME: &str = "$optical::TrailingOption";
+
+/// An optional value at the end of a packet, which is present if the packet has bytes left, and
+/// absent if the packet ends.
+///
+/// Unlike `Option`, no boolean prefix is written. This is used for fields which were appended to a
+/// packet in a later protocol version, so older clients simply don't send them. Only the last
+/// fields of a packet can be trailing options.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct TrailingOption<T>(pub Option<T>);
+
+impl<T> From<Option<T>> for TrailingOption<T> {
+    fn from(value: Option<T>) -> Self {
+        return TrailingOption(value);
+    }
+}
+
+/// An optional number, sent as a var int which is 0 when the number is absent.
+///
+/// A present number can't be 0, serializing `Some(0)` fails.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OptionalVarInt(pub Option<i32>);
+
+impl From<Option<i32>> for OptionalVarInt {
+    fn from(value: Option<i32>) -> Self {
+        return OptionalVarInt(value);
+    }
+}
+
+/// A field which decides if the fields after it are present, like a boolean or a var int count.
+pub trait Condition {
+    /// Are the fields which depend on this one present?
+    fn is_present(&self) -> bool;
+}
+
+impl Condition for bool {
+    fn is_present(&self) -> bool {
+        return *self;
+    }
+}
+
+impl Condition for VarInt {
+    fn is_present(&self) -> bool {
+        return self.value != 0;
+    }
+}
+
+/// A value which is only present when a field in front of it says so.
+///
+/// The `condition` is always sent, followed by the `value` when [`Condition::is_present`] is true.
+/// Multiple fields can depend on the same condition by making `T` a tuple or struct. Custom
+/// conditions, like an enum where only some variants are followed by data, implement
+/// [`Condition`] themselves.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ConditionalOn<C, T> {
+    pub condition: C,
+    pub value: Option<T>,
+}
+
+impl<C: Condition, T> ConditionalOn<C, T> {
+    /// Creates a new value, returns None if the presence of `value` doesn't match the condition.
+    pub fn new(condition: C, value: Option<T>) -> Option<Self> {
+        if condition.is_present() != value.is_some() {
+            return None;
+        }
+        return Some(ConditionalOn { condition, value });
+    }
+}
+
+/// The name given to [`Json`] when serialized as a newtype struct. The optical serializer and
+/// deserializer look for this name to convert the inner value from and to a JSON string.
+pub(crate) const JSON_NAME: &str = "$optical::Json";
+
+/// A value sent as a JSON document inside a protocol string, like status responses and chat.
+///
+/// Other formats see the inner value as it is, so a packet holding a `Json<T>` field can itself be
+/// rendered as JSON without the value being escaped into a string.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+impl<T> From<T> for Json<T> {
+    fn from(value: T) -> Self {
+        return Json(value);
+    }
+}
+
+/// How the discriminant of an enum is sent, picked through a marker the enum is renamed to.
+///
+/// By default, variants are sent as a var int holding their declaration index. Renaming the enum
+/// with `#[serde(rename = "...")]` to `$optical::u8`, `$optical::i32` or `$optical::var_int` picks
+/// the type, optionally followed by the protocol id of each variant in declaration order, so
+/// protocol ids with gaps can be used as they are. Enums renamed to `$optical::identifier` send the
+/// name of the variant as a string. Like the other `$optical::` names, the marker only means
+/// something to the optical format, other formats like JSON see the variants by their names.
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// #[serde(rename = "$optical::u8(0, 3)")]
+/// pub enum GameMode {
+///     Survival,
+///     Spectator,
+/// }
+///
+/// #[derive(Serialize, Deserialize)]
+/// #[serde(rename = "$optical::identifier")]
+/// pub enum Dimension {
+///     #[serde(rename = "minecraft:overworld")]
+///     Overworld,
+///     #[serde(rename = "minecraft:the_nether")]
+///     Nether,
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscriminantKind {
+    /// A var int, the default, also picked with `$optical::var_int`.
+    VarInt,
+    /// An unsigned byte, picked with `$optical::u8`.
+    U8,
+    /// A 32 bit signed integer, picked with `$optical::i32`.
+    I32,
+    /// A string holding the variant name, picked with `$optical::identifier`.
+    Identifier,
+}
+
+impl DiscriminantKind {
+    /// Returns the discriminant kind picked by the name of an enum.
+    pub fn of(enum_name: &str) -> Self {
+        let marker = match enum_name.split_once('(') {
+            Some((marker, _)) => marker,
+            None => enum_name,
+        };
+        return match marker {
+            "$optical::u8" => DiscriminantKind::U8,
+            "$optical::i32" => DiscriminantKind::I32,
+            "$optical::identifier" => DiscriminantKind::Identifier,
+            _ => DiscriminantKind::VarInt,
+        };
+    }
+}
+
+/// Returns the protocol ids listed in the marker of an enum, or None if it lists none.
+fn listed_ids(enum_name: &'static str) -> Option<impl Iterator<Item = Result<i64, Error>>> {
+    let (_, ids) = enum_name.strip_prefix("$optical::")?.split_once('(')?;
+    let ids = ids.strip_suffix(')')?;
+    return Some(ids.split(',').map(move |id| {
+        return id
+            .trim()
+            .parse()
+            .map_err(|_| Error::InvalidEnumMarker(enum_name));
+    }));
+}
+
+/// Returns the number a variant is sent as, either the id listed for it in the marker of its
+/// enum, or its declaration index.
+pub(crate) fn variant_discriminant(
+    enum_name: &'static str,
+    variant_index: u32,
+) -> Result<i64, Error> {
+    let mut ids = match listed_ids(enum_name) {
+        Some(ids) => ids,
+        None => return Ok(variant_index.into()),
+    };
+    return ids
+        .nth(variant_index as usize)
+        .unwrap_or(Err(Error::InvalidEnumMarker(enum_name)));
+}
+
+/// Returns the name of the variant sent as `discriminant`, looked up like
+/// [`variant_discriminant`].
+pub(crate) fn variant_name(
+    enum_name: &'static str,
+    variants: &'static [&'static str],
+    discriminant: i64,
+) -> Result<&'static str, Error> {
+    let index = match listed_ids(enum_name) {
+        Some(ids) => {
+            let mut index = None;
+            for (i, id) in ids.enumerate() {
+                if id? == discriminant {
+                    index = Some(i);
+                    break;
+                }
+            }
+            index
+        }
+        None => usize::try_from(discriminant).ok(),
+    };
+    return index
+        .and_then(|i| variants.get(i))
+        .copied()
+        .ok_or(Error::UnknownDiscriminant(discriminant));
+}
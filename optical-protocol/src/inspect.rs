@@ -0,0 +1,142 @@
+//! Decoding packets of any type, for tools which inspect captured traffic.
+//!
+//! Which packet a frame holds is picked by the protocol state and direction it was sent in, and
+//! the packet id in the frame. A decoded [`Packet`] can be printed with `Debug`, or serialized with
+//! any serde format, where it shows up tagged with its id: `{"type":"0","adjacent":{...}}`.
+
+use crate::{
+    format::{
+        cursor::Cursor,
+        deserializer::{from_bytes_generic_with_mode, TrailingBytesMode},
+        error::Error,
+        tags::{
+            ClientLoginPacket, ClientPlayPacket, ClientStatusPacket, ClientVoidPacket, LoginPacket,
+            PlayPacket, StatusPacket, VoidPacket,
+        },
+    },
+    packets::{
+        login::clientbound::LoginSuccess, void::serverbound::Handshake, Direction, ProtocolState,
+    },
+};
+use serde::{Serialize, Serializer};
+
+/// A packet of any protocol state and direction.
+#[derive(Debug)]
+pub enum Packet {
+    Void(Box<dyn VoidPacket>),
+    Status(Box<dyn StatusPacket>),
+    Login(Box<dyn LoginPacket>),
+    Play(Box<dyn PlayPacket>),
+    ClientVoid(Box<dyn ClientVoidPacket>),
+    ClientStatus(Box<dyn ClientStatusPacket>),
+    ClientLogin(Box<dyn ClientLoginPacket>),
+    ClientPlay(Box<dyn ClientPlayPacket>),
+}
+
+impl Packet {
+    pub fn packet_id(&self) -> i32 {
+        return match self {
+            Packet::Void(p) => p.packet_id(),
+            Packet::Status(p) => p.packet_id(),
+            Packet::Login(p) => p.packet_id(),
+            Packet::Play(p) => p.packet_id(),
+            Packet::ClientVoid(p) => p.packet_id(),
+            Packet::ClientStatus(p) => p.packet_id(),
+            Packet::ClientLogin(p) => p.packet_id(),
+            Packet::ClientPlay(p) => p.packet_id(),
+        };
+    }
+}
+
+impl Serialize for Packet {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        return match self {
+            Packet::Void(p) => p.serialize(serializer),
+            Packet::Status(p) => p.serialize(serializer),
+            Packet::Login(p) => p.serialize(serializer),
+            Packet::Play(p) => p.serialize(serializer),
+            Packet::ClientVoid(p) => p.serialize(serializer),
+            Packet::ClientStatus(p) => p.serialize(serializer),
+            Packet::ClientLogin(p) => p.serialize(serializer),
+            Packet::ClientPlay(p) => p.serialize(serializer),
+        };
+    }
+}
+
+/// Decodes a single framed packet, sent in `state` towards `direction`.
+pub fn decode(
+    state: ProtocolState,
+    direction: Direction,
+    input: &mut Cursor<Vec<u8>>,
+) -> Result<Packet, Error> {
+    return decode_with_mode(state, direction, input, TrailingBytesMode::default());
+}
+
+/// Like [`decode`], but with a specific way to handle trailing bytes.
+pub fn decode_with_mode(
+    state: ProtocolState,
+    direction: Direction,
+    input: &mut Cursor<Vec<u8>>,
+    mode: TrailingBytesMode,
+) -> Result<Packet, Error> {
+    use Direction::*;
+    use ProtocolState::*;
+
+    return Ok(match (state, direction) {
+        (Void, Serverbound) => Packet::Void(from_bytes_generic_with_mode(input, mode)?),
+        (Status, Serverbound) => Packet::Status(from_bytes_generic_with_mode(input, mode)?),
+        (Login, Serverbound) => Packet::Login(from_bytes_generic_with_mode(input, mode)?),
+        (Play, Serverbound) => Packet::Play(from_bytes_generic_with_mode(input, mode)?),
+        (Void, Clientbound) => Packet::ClientVoid(from_bytes_generic_with_mode(input, mode)?),
+        (Status, Clientbound) => Packet::ClientStatus(from_bytes_generic_with_mode(input, mode)?),
+        (Login, Clientbound) => Packet::ClientLogin(from_bytes_generic_with_mode(input, mode)?),
+        (Play, Clientbound) => Packet::ClientPlay(from_bytes_generic_with_mode(input, mode)?),
+    });
+}
+
+/// Follows the protocol state of a connection through the packets sent on it.
+///
+/// A handshake moves the connection to the state it asks for, and a login success moves it to the
+/// play state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateTracker {
+    pub state: ProtocolState,
+}
+
+impl Default for StateTracker {
+    fn default() -> Self {
+        return StateTracker {
+            state: ProtocolState::Void,
+        };
+    }
+}
+
+impl StateTracker {
+    /// Moves to the next state, if `packet` changes the state of the connection.
+    pub fn observe(&mut self, packet: &Packet) {
+        match packet {
+            Packet::Void(p) => {
+                if let Some(handshake) = p.as_any().downcast_ref::<Handshake>() {
+                    match handshake.next_state.value {
+                        1 => self.state = ProtocolState::Status,
+                        2 => self.state = ProtocolState::Login,
+                        _ => {}
+                    }
+                }
+            }
+            Packet::ClientLogin(p) if p.as_any().is::<LoginSuccess>() => {
+                self.state = ProtocolState::Play;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Formats bytes as lowercase hex, without separators, the way the tools print frames which
+/// don't decode.
+pub fn hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|b| format!("{b:02x}")).collect();
+}
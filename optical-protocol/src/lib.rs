@@ -5,11 +5,36 @@
 //! like a TCP listener. One might choose to use this library to write their own Minecraft server/client
 //! implementations, or to define their own custom packets for mods.
 //!
+//! # Features
+//!
+//! - `std` (default): the packet registries, which deserialize packets into trait objects by
+//!   their id, decoding packets of any type through [`inspect`], [`capture`] files, and reading
+//!   from a [`std::io::Cursor`].
+//! - `server` (default): the TCP listener in [`server`], built on tokio.
+//! - `client` (default): the async [`client`] for bots and for testing servers, built on tokio.
+//! - `stream`: framing packets on a TCP [`stream`] with encryption and compression. Enabled by
+//!   both `server` and `client`.
+//!
+//! Without any features the crate is `no_std`, and only needs `alloc`. The data format and the
+//! packet definitions can then be used in places without an OS, like WebAssembly tools.
+//!
 //! [`Serde`]: https://docs.rs/serde/latest/serde/
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod format;
+#[cfg(feature = "std")]
+pub mod inspect;
 pub mod packets;
+#[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "stream")]
+pub mod stream;
@@ -8,3 +8,28 @@ mod non_play;
 mod play;
 pub use non_play::*;
 pub use play::*;
+
+/// The protocol version these packets are defined for, which is Minecraft 1.19.3.
+pub const PROTOCOL_VERSION: i32 = 761;
+
+/// The current state that a connection between a client and the server is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolState {
+    /// The client and server handshake
+    Void,
+    /// The server provides some information about itself for the Minecraft server list
+    Status,
+    /// The client attempts to join the server
+    Login,
+    /// The client is playing on the server
+    Play,
+}
+
+/// The side a packet is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Sent by the client to the server
+    Serverbound,
+    /// Sent by the server to the client
+    Clientbound,
+}
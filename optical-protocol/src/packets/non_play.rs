@@ -1,16 +1,21 @@
 pub mod void {
     pub mod serverbound {
+        use alloc::string::String;
+
         use serde::{Deserialize, Serialize};
 
-        use crate::format::{tags::VoidPacket, types::VarInt};
+        #[cfg(feature = "std")]
+        use crate::format::tags::VoidPacket;
+        use crate::format::types::VarInt;
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct Handshake {
             pub protocol_version: VarInt,
             pub server_address: String,
             pub server_port: u16,
             pub next_state: VarInt,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "0")]
         impl VoidPacket for Handshake {}
     }
@@ -18,38 +23,84 @@ pub mod void {
 
 pub mod status {
     pub mod clientbound {
+        use alloc::{string::String, vec::Vec};
+
+        #[cfg(feature = "std")]
         use crate::format::tags::ClientStatusPacket;
+        use crate::format::types::Json;
         use serde::Deserialize;
         use serde::Serialize;
 
-        #[derive(Serialize, Deserialize, Debug)]
+        /// The status of the server shown in the server list, sent as JSON.
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        pub struct ServerStatus {
+            pub version: StatusVersion,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub players: Option<StatusPlayers>,
+            /// The message of the day, as a chat component.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub description: Option<serde_json::Value>,
+            /// A PNG image, encoded as a `data:image/png;base64,` URI.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub favicon: Option<String>,
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub enforces_secure_chat: Option<bool>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct StatusVersion {
+            pub name: String,
+            pub protocol: i32,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct StatusPlayers {
+            pub max: i32,
+            pub online: i32,
+            #[serde(default, skip_serializing_if = "Vec::is_empty")]
+            pub sample: Vec<StatusPlayerSample>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct StatusPlayerSample {
+            pub name: String,
+            pub id: String,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct StatusResponse {
-            pub json_response: String,
+            pub status: Json<ServerStatus>,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "0")]
         impl ClientStatusPacket for StatusResponse {}
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct PingResponse {
             pub payload: i64,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "1")]
         impl ClientStatusPacket for PingResponse {}
     }
 
     pub mod serverbound {
+        #[cfg(feature = "std")]
         use crate::format::tags::StatusPacket;
         use serde::{Deserialize, Serialize};
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct StatusRequest {}
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "0")]
         impl StatusPacket for StatusRequest {}
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct PingRequest {
-            payload: i64,
+            pub payload: i64,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "1")]
         impl StatusPacket for PingRequest {}
     }
@@ -57,95 +108,104 @@ pub mod status {
 
 pub mod login {
     pub mod clientbound {
-        use crate::format::{
-            tags::{ClientLoginPacket, LoginPacket},
-            types::{Bytes, MinecraftUuid, VarInt},
-        };
+        use alloc::{string::String, vec::Vec};
+
+        #[cfg(feature = "std")]
+        use crate::format::tags::ClientLoginPacket;
+        use crate::format::types::{Bytes, MinecraftUuid, VarInt};
         use serde::{Deserialize, Serialize};
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct Disconnect {
             pub reason: String,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "0")]
         impl ClientLoginPacket for Disconnect {}
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct EncryptionRequest {
             pub server_id: String,
             pub public_key: Vec<u8>,
             pub verify_token: Vec<u8>,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "1")]
         impl ClientLoginPacket for EncryptionRequest {}
 
-        #[derive(Serialize, Deserialize, Debug)]
-        pub enum LoginSuccessProperties {
-            None,
-            One {
-                name: String,
-            },
-            Two {
-                name: String,
-                value: String,
-            },
-            Three {
-                name: String,
-                value: String,
-                is_signed: bool,
-            },
-            Four {
-                name: String,
-                value: String,
-                signature: Option<String>,
-            },
-        }
-
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+        pub struct LoginSuccessProperty {
+            pub name: String,
+            pub value: String,
+            pub signature: Option<String>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct LoginSuccess {
-            uuid: MinecraftUuid,
-            username: String,
-            properties: LoginSuccessProperties,
+            pub uuid: MinecraftUuid,
+            pub username: String,
+            pub properties: Vec<LoginSuccessProperty>,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "2")]
         impl ClientLoginPacket for LoginSuccess {}
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct SetCompression {
             pub threshold: VarInt,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "3")]
         impl ClientLoginPacket for SetCompression {}
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct LoginPluginRequest {
-            message_id: VarInt,
-            channel: String,
-            data: Bytes,
+            pub message_id: VarInt,
+            pub channel: String,
+            pub data: Bytes,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "4")]
         impl ClientLoginPacket for LoginPluginRequest {}
     }
 
     pub mod serverbound {
+        use alloc::{string::String, vec::Vec};
+
         use serde::{Deserialize, Serialize};
 
-        use crate::format::{tags::LoginPacket, types::MinecraftUuid};
+        #[cfg(feature = "std")]
+        use crate::format::tags::LoginPacket;
+        use crate::format::types::{Bytes, MinecraftUuid, TrailingOption, VarInt};
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct LoginStart {
             pub name: String,
-            pub player_uuid: Option<MinecraftUuid>,
+            /// Clients before 1.19.1 don't send this field at all, newer clients prefix it with
+            /// a boolean.
+            pub player_uuid: TrailingOption<Option<MinecraftUuid>>,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "0")]
         impl LoginPacket for LoginStart {}
 
-        #[derive(Serialize, Deserialize, Debug)]
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
         pub struct EncryptionResponse {
             pub shared_secret: Vec<u8>,
             pub verify_token: Vec<u8>,
         }
+        #[cfg(feature = "std")]
         #[typetag::serde(name = "1")]
         impl LoginPacket for EncryptionResponse {}
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        pub struct LoginPluginResponse {
+            pub message_id: VarInt,
+            /// The answer to the request, or None if the client doesn't understand its channel.
+            pub data: Option<Bytes>,
+        }
+        #[cfg(feature = "std")]
+        #[typetag::serde(name = "2")]
+        impl LoginPacket for LoginPluginResponse {}
     }
 }
@@ -1,3 +1,83 @@
-pub mod clientbound {}
+use serde::{Deserialize, Serialize};
 
-pub mod serverbound {}
+pub mod clientbound {
+    use alloc::string::String;
+
+    #[cfg(feature = "std")]
+    use crate::format::tags::ClientPlayPacket;
+    use crate::format::types::Bytes;
+    use serde::{Deserialize, Serialize};
+
+    /// A message on a plugin channel, which mods and proxies talk through.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct PluginMessage {
+        pub channel: String,
+        pub data: Bytes,
+    }
+    #[cfg(feature = "std")]
+    #[typetag::serde(name = "21")]
+    impl ClientPlayPacket for PluginMessage {}
+
+    /// Sends the client away. The reason is a chat component, sent as JSON.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct Disconnect {
+        pub reason: String,
+    }
+    #[cfg(feature = "std")]
+    #[typetag::serde(name = "23")]
+    impl ClientPlayPacket for Disconnect {}
+
+    /// Checks the client is still there. It answers with the same id.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct KeepAlive {
+        pub id: i64,
+    }
+    #[cfg(feature = "std")]
+    #[typetag::serde(name = "31")]
+    impl ClientPlayPacket for KeepAlive {}
+}
+
+pub mod serverbound {
+    use alloc::string::String;
+
+    #[cfg(feature = "std")]
+    use crate::format::tags::PlayPacket;
+    use crate::format::types::Bytes;
+    use serde::{Deserialize, Serialize};
+
+    /// A message on a plugin channel, which mods and proxies talk through.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct PluginMessage {
+        pub channel: String,
+        pub data: Bytes,
+    }
+    #[cfg(feature = "std")]
+    #[typetag::serde(name = "12")]
+    impl PlayPacket for PluginMessage {}
+
+    /// The answer to a keep alive of the server, with its id.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct KeepAlive {
+        pub id: i64,
+    }
+    #[cfg(feature = "std")]
+    #[typetag::serde(name = "17")]
+    impl PlayPacket for KeepAlive {}
+}
+
+/// The game mode of a player, sent as an unsigned byte.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename = "$optical::u8")]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+/// The hand a player used, sent as a var int.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hand {
+    MainHand,
+    OffHand,
+}
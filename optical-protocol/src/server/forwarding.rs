@@ -0,0 +1,195 @@
+//! Player info forwarding, through which a proxy in front of the server tells it who is joining.
+//!
+//! Behind a proxy, every connection comes from the proxy, and the proxy authenticates players
+//! itself. It forwards the address, UUID and skin properties of the player, either with
+//! BungeeCord's legacy forwarding, or Velocity's modern forwarding:
+//!
+//! - Legacy forwarding packs them into the server address of the handshake, separated by null
+//!   characters: `host\0address\0uuid\0properties`. It isn't signed, so anyone who can reach the
+//!   server can claim to be anyone. With a secret, the properties must hold it as a
+//!   `bungeeguard-token`, as the BungeeGuard plugin sends it.
+//! - Modern forwarding sends them in the response to a login plugin request on
+//!   [`VELOCITY_CHANNEL`], signed with HMAC-SHA256 and a secret shared with the proxy.
+
+use std::{io::Cursor, net::IpAddr};
+
+use anyhow::{anyhow, bail, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    format::{
+        deserializer, serializer,
+        types::{MinecraftUuid, VarInt},
+    },
+    packets::login::clientbound::LoginSuccessProperty,
+};
+
+/// The login plugin channel Velocity forwards player info on.
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+/// The version of Velocity's forwarding requested, which forwards everything but the chat
+/// signing key.
+pub const VELOCITY_VERSION: u8 = 1;
+/// The property BungeeGuard sends its token as.
+pub const BUNGEEGUARD_PROPERTY: &str = "bungeeguard-token";
+
+/// How the listener learns about players joining through a proxy.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Forwarding {
+    /// Players connect directly.
+    #[default]
+    Disabled,
+    /// BungeeCord's legacy forwarding, with the BungeeGuard plugin. Players without a token
+    /// matching the secret are rejected.
+    Legacy { secret: String },
+    /// BungeeCord's legacy forwarding, without BungeeGuard.
+    ///
+    /// **Anyone who can reach the server can join as any player, with any skin.** Only use this
+    /// when nothing but the proxy can connect to the server, like behind a firewall. The listener
+    /// logs a warning when it starts with it.
+    LegacyInsecure,
+    /// Velocity's modern forwarding, signed with the secret.
+    Modern { secret: String },
+}
+
+impl Forwarding {
+    /// Checks the forwarding can be trusted. An empty secret would let anyone sign forwarding, or
+    /// send a blank BungeeGuard token.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Forwarding::Legacy { secret } | Forwarding::Modern { secret } if secret.is_empty() => {
+                bail!("the forwarding secret is empty");
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// A player a proxy forwarded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardedPlayer {
+    /// The address the player connected to the proxy from.
+    pub address: IpAddr,
+    pub uuid: Uuid,
+    pub username: String,
+    pub properties: Vec<LoginSuccessProperty>,
+}
+
+/// The data of Velocity's response, after its signature.
+#[derive(Serialize, Deserialize)]
+struct VelocityPlayerInfo {
+    version: VarInt,
+    address: String,
+    uuid: MinecraftUuid,
+    username: String,
+    properties: Vec<LoginSuccessProperty>,
+}
+
+impl ForwardedPlayer {
+    /// Parses the server address of a handshake with legacy forwarding. The username isn't
+    /// forwarded, and comes from the `LoginStart` packet.
+    pub fn from_legacy(
+        server_address: &str,
+        username: &str,
+        secret: Option<&str>,
+    ) -> Result<ForwardedPlayer> {
+        let fields: Vec<&str> = server_address.split('\0').collect();
+        let (address, uuid, properties) = match fields[..] {
+            [_host, address, uuid] => (address, uuid, "[]"),
+            [_host, address, uuid, properties] => (address, uuid, properties),
+            _ => bail!("the handshake doesn't hold forwarded player info"),
+        };
+        let mut properties: Vec<LoginSuccessProperty> = serde_json::from_str(properties)?;
+
+        if let Some(secret) = secret {
+            // The token is only meant for us, so it's taken out of the properties.
+            let tokens: Vec<LoginSuccessProperty>;
+            (tokens, properties) = properties
+                .into_iter()
+                .partition(|p| p.name == BUNGEEGUARD_PROPERTY);
+            match &tokens[..] {
+                [token] if constant_time_eq(token.value.as_bytes(), secret.as_bytes()) => {}
+                [_] => bail!("the forwarded BungeeGuard token is wrong"),
+                _ => bail!("the forwarded player info doesn't hold exactly one BungeeGuard token"),
+            }
+        }
+
+        return Ok(ForwardedPlayer {
+            address: address.parse()?,
+            uuid: Uuid::parse_str(uuid)?,
+            username: username.to_string(),
+            properties,
+        });
+    }
+
+    /// Writes the server address of a handshake with legacy forwarding.
+    pub fn to_legacy(&self, host: &str, secret: Option<&str>) -> Result<String> {
+        let mut properties = self.properties.clone();
+        if let Some(secret) = secret {
+            properties.push(LoginSuccessProperty {
+                name: BUNGEEGUARD_PROPERTY.to_string(),
+                value: secret.to_string(),
+                signature: None,
+            });
+        }
+        return Ok(format!(
+            "{host}\0{}\0{}\0{}",
+            self.address,
+            self.uuid.simple(),
+            serde_json::to_string(&properties)?
+        ));
+    }
+
+    /// Verifies and parses the data of Velocity's response to a player info request.
+    pub fn from_modern(data: &[u8], secret: &str) -> Result<ForwardedPlayer> {
+        if data.len() < 32 {
+            bail!("the forwarded player info is too short to be signed");
+        }
+        let (signature, info) = data.split_at(32);
+        let mut mac = mac(secret)?;
+        mac.update(info);
+        mac.verify_slice(signature)
+            .map_err(|_| anyhow!("the forwarded player info has an invalid signature"))?;
+
+        let info: VelocityPlayerInfo = deserializer::from_payload(&mut Cursor::new(info.to_vec()))?;
+        if info.version.value != VELOCITY_VERSION as i32 {
+            bail!("unsupported forwarding version {}", info.version.value);
+        }
+        return Ok(ForwardedPlayer {
+            address: info.address.parse()?,
+            uuid: info.uuid.0,
+            username: info.username,
+            properties: info.properties,
+        });
+    }
+
+    /// Writes and signs the data of a response to a player info request, as Velocity does.
+    pub fn to_modern(&self, secret: &str) -> Result<Vec<u8>> {
+        let info = serializer::to_payload(&VelocityPlayerInfo {
+            version: (VELOCITY_VERSION as i32).into(),
+            address: self.address.to_string(),
+            uuid: MinecraftUuid(self.uuid),
+            username: self.username.clone(),
+            properties: self.properties.clone(),
+        })?;
+        let mut mac = mac(secret)?;
+        mac.update(&info);
+        let mut data = mac.finalize().into_bytes().to_vec();
+        data.extend(info);
+        return Ok(data);
+    }
+}
+
+fn mac(secret: &str) -> Result<Hmac<Sha256>> {
+    return Hmac::new_from_slice(secret.as_bytes()).map_err(|e| anyhow!("{e}"));
+}
+
+/// Compares secrets without leaking through timing how much of them matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    return a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0;
+}
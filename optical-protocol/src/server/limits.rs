@@ -0,0 +1,219 @@
+//! Limits on how many connections clients open, and how much they send through them.
+//!
+//! The listener counts connections per IP address, as told by the PROXY header when there is one,
+//! and the packets and bytes each connection sends per second. Clients over a limit are
+//! disconnected, and the violation is logged.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::packets::ProtocolState;
+
+/// The limits of [`ListenerConfig::limits`](super::ListenerConfig::limits). Every limit is off by
+/// default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// The most connections one IP address may open within [`Limits::connection_window`].
+    pub connections_per_ip: Option<usize>,
+    /// The window of [`Limits::connections_per_ip`], a minute by default. Listeners with a
+    /// window of zero and a `connections_per_ip` fail to start.
+    pub connection_window: Duration,
+    /// The most connections one IP address may have open at once.
+    pub concurrent_per_ip: Option<usize>,
+    /// The most connections open at once.
+    pub max_connections: Option<usize>,
+    /// How much connections may send per second, in each protocol state.
+    pub packet_rates: HashMap<ProtocolState, RateLimit>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        return Limits {
+            connections_per_ip: None,
+            connection_window: Duration::from_secs(60),
+            concurrent_per_ip: None,
+            max_connections: None,
+            packet_rates: HashMap::new(),
+        };
+    }
+}
+
+/// How much a connection may send per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub packets_per_second: u32,
+    pub bytes_per_second: u64,
+}
+
+/// The connections open, and recently opened, shared by the listener's tasks.
+#[derive(Debug, Default)]
+struct Tracker {
+    open: usize,
+    per_ip: HashMap<IpAddr, IpConnections>,
+    /// How many addresses are tracked before the ones which went idle are swept. Doubles with the
+    /// addresses left after each sweep, so sweeps stay rare during a flood.
+    sweep_at: usize,
+}
+
+/// The least amount of tracked addresses swept.
+const MIN_SWEEP: usize = 64;
+
+impl IpConnections {
+    /// Forgets connections opened before the window.
+    fn prune(&mut self, now: Instant, window: Duration) {
+        while self.recent.front().is_some_and(|t| now - *t >= window) {
+            self.recent.pop_front();
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        return self.open == 0 && self.recent.is_empty();
+    }
+}
+
+#[derive(Debug, Default)]
+struct IpConnections {
+    open: usize,
+    /// When connections were opened, within the connection window.
+    recent: VecDeque<Instant>,
+}
+
+/// Counts the connections of the listener against its limits.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionLimiter {
+    limits: Arc<Limits>,
+    tracker: Arc<Mutex<Tracker>>,
+}
+
+/// A connection counted by a [`ConnectionLimiter`], until it's dropped.
+#[derive(Debug)]
+pub(crate) struct ConnectionSlot {
+    tracker: Arc<Mutex<Tracker>>,
+    ip: Option<IpAddr>,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(limits: Limits) -> Result<Self> {
+        if limits.connections_per_ip.is_some() && limits.connection_window.is_zero() {
+            bail!("connections_per_ip needs a connection_window longer than zero");
+        }
+        return Ok(ConnectionLimiter {
+            limits: Arc::new(limits),
+            tracker: Arc::default(),
+        });
+    }
+
+    /// Counts a new connection, unless the listener is full.
+    pub(crate) fn open(&self) -> Result<ConnectionSlot> {
+        let mut tracker = self.tracker.lock().unwrap();
+        if let Some(max) = self.limits.max_connections {
+            if tracker.open >= max {
+                bail!("the server is full with {max} connections");
+            }
+        }
+        tracker.open += 1;
+        return Ok(ConnectionSlot {
+            tracker: self.tracker.clone(),
+            ip: None,
+        });
+    }
+
+    /// Counts a connection against the limits of the IP address it comes from.
+    pub(crate) fn admit(&self, slot: &mut ConnectionSlot, ip: IpAddr) -> Result<()> {
+        let window = self.limits.connection_window;
+        let now = Instant::now();
+        let mut tracker = self.tracker.lock().unwrap();
+        // Forget addresses which have nothing open, or recently opened.
+        if tracker.per_ip.len() >= tracker.sweep_at.max(MIN_SWEEP) {
+            tracker.per_ip.retain(|_, c| {
+                c.prune(now, window);
+                return !c.is_idle();
+            });
+            tracker.sweep_at = tracker.per_ip.len() * 2;
+        }
+
+        let connections = tracker.per_ip.entry(ip).or_default();
+        connections.prune(now, window);
+        if let Some(max) = self.limits.concurrent_per_ip {
+            if connections.open >= max {
+                bail!("{ip} already has {max} connections open");
+            }
+        }
+        if let Some(max) = self.limits.connections_per_ip {
+            if connections.recent.len() >= max {
+                bail!("{ip} opened more than {max} connections in {window:?}");
+            }
+        }
+        connections.open += 1;
+        connections.recent.push_back(now);
+        slot.ip = Some(ip);
+        return Ok(());
+    }
+
+    pub(crate) fn packet_rate(&self, state: ProtocolState) -> Option<RateLimit> {
+        return self.limits.packet_rates.get(&state).copied();
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut tracker = self.tracker.lock().unwrap();
+        tracker.open -= 1;
+        if let Some(ip) = self.ip {
+            if let Some(connections) = tracker.per_ip.get_mut(&ip) {
+                connections.open -= 1;
+            }
+        }
+    }
+}
+
+/// The packets and bytes a connection sent in the current second.
+#[derive(Debug)]
+pub(crate) struct RateCounter {
+    started: Instant,
+    packets: u32,
+    bytes: u64,
+}
+
+impl RateCounter {
+    pub(crate) fn new() -> Self {
+        return RateCounter {
+            started: Instant::now(),
+            packets: 0,
+            bytes: 0,
+        };
+    }
+
+    /// Counts a packet of `length` bytes, which fails when it goes over the limit.
+    pub(crate) fn count(&mut self, limit: RateLimit, length: usize) -> Result<()> {
+        let now = Instant::now();
+        if now - self.started >= Duration::from_secs(1) {
+            *self = RateCounter {
+                started: now,
+                packets: 0,
+                bytes: 0,
+            };
+        }
+        self.packets += 1;
+        self.bytes += length as u64;
+        if self.packets > limit.packets_per_second {
+            bail!(
+                "sent more than {} packets in a second",
+                limit.packets_per_second
+            );
+        }
+        if self.bytes > limit.bytes_per_second {
+            bail!(
+                "sent more than {} bytes in a second",
+                limit.bytes_per_second
+            );
+        }
+        return Ok(());
+    }
+}
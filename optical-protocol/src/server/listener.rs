@@ -1,47 +1,171 @@
 use std::{
+    future::{self, Future},
     io::Cursor,
-    sync::mpsc::{self, Receiver, Sender},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::{
-    format::tags::{ClientLoginPacket, LoginPacket},
-    packets::login::serverbound::EncryptionResponse,
+use super::{
+    forwarding::{ForwardedPlayer, Forwarding, VELOCITY_CHANNEL, VELOCITY_VERSION},
+    limits::{ConnectionLimiter, Limits, RateCounter},
+    login_plugin::LoginChannel,
+    proxy_protocol::{self, ProxyHeader},
 };
 use crate::{
-    format::{deserializer, serializer, types::read_var_int},
+    capture::{self, Frame},
+    format::{
+        deserializer, framing, serializer,
+        types::{Bytes, MinecraftUuid},
+    },
     packets::{
-        login::{clientbound::EncryptionRequest, serverbound::LoginStart},
+        login::{
+            clientbound::{Disconnect, EncryptionRequest, LoginPluginRequest, LoginSuccess},
+            serverbound::{LoginPluginResponse, LoginStart},
+        },
         void::serverbound::Handshake,
+        Direction,
     },
 };
-use anyhow::{anyhow, Result};
-use async_recursion::async_recursion;
+use crate::{
+    format::tags::{ClientLoginPacket, ClientPlayPacket, LoginPacket},
+    packets::clientbound,
+    packets::login::serverbound::EncryptionResponse,
+};
+use anyhow::{anyhow, bail, Result};
+use bytes::BytesMut;
 use pkcs1::EncodeRsaPublicKey;
 use rsa::{PaddingScheme, RsaPrivateKey, RsaPublicKey};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
     net::{TcpListener, TcpStream},
     runtime::Runtime,
-    sync::Mutex,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
     task::JoinHandle,
+    time::{self, Instant},
 };
 use unwrap_or::unwrap_some_or;
 
-/// The current state that a connection between a client and the server is in.
-pub enum ProtocolState {
-    /// The client and server handshake
-    Void,
-    /// The server provides some information about itself for the Minecraft server list
-    Status,
-    /// The client attempts to join the server
-    Login,
-    /// The client is playing on the server
-    Play,
+pub use crate::packets::ProtocolState;
+pub use crate::stream::split_packet;
+
+/// The reason clients still logging in are given when the listener stops.
+pub const SHUTDOWN_REASON: &str = "Server closed";
+
+/// A client handed over by the listener.
+pub struct Connection {
+    pub state: ProtocolState,
+    pub packets: Receiver<Cursor<Vec<u8>>>,
+    /// Complete frames to write to the client. Dropping it closes the connection once the frames
+    /// sent before are written.
+    pub outgoing: UnboundedSender<Vec<u8>>,
+    /// The address of the client, as told by the PROXY header or the forwarded player info when
+    /// there is one.
+    pub address: SocketAddr,
+    /// The player a proxy forwarded, when [`ListenerConfig::forwarding`] is enabled.
+    pub player: Option<ForwardedPlayer>,
+}
+
+/// Whether connections start with a PROXY protocol header. See [`proxy_protocol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProxyProtocol {
+    /// Connections never start with a header.
+    #[default]
+    Disabled,
+    /// A header is parsed when there is one. Any client can then claim any address, so this is
+    /// only meant for moving a server behind a load balancer.
+    Optional,
+    /// Connections without a header are closed.
+    Required,
 }
 
-pub type Connection = (ProtocolState, Receiver<Cursor<Vec<u8>>>);
+/// Options for [`start_with_config`].
+#[derive(Debug, Clone)]
+pub struct ListenerConfig {
+    /// The address the listener binds to.
+    pub address: String,
+    /// When set, the frames of every connection are recorded into a capture file in this
+    /// directory. See [`capture`](crate::capture).
+    pub capture_dir: Option<PathBuf>,
+    /// Whether the listener sits behind a load balancer sending PROXY headers.
+    pub proxy_protocol: ProxyProtocol,
+    /// Whether the listener sits behind a proxy forwarding player info. Forwarded players skip
+    /// encryption, as the proxy authenticated them, and are handed over in the Play state.
+    pub forwarding: Forwarding,
+    /// The login plugin channels sending a request to every player logging in. See
+    /// [`login_plugin`](super::login_plugin).
+    pub login_channels: Vec<Arc<dyn LoginChannel>>,
+    /// How long players have to answer the login plugin requests, including the one of modern
+    /// forwarding.
+    pub login_plugin_timeout: Duration,
+    /// How long clients have to send the PROXY header and the handshake after connecting.
+    pub handshake_timeout: Duration,
+    /// How long clients have to send each packet of the login, like the encryption response.
+    pub login_timeout: Duration,
+    /// How long a connection in the Status state stays open.
+    pub status_timeout: Duration,
+    /// Limits on connections and their traffic. See [`limits`](super::limits).
+    pub limits: Limits,
+    /// Once this holds true, the listener stops accepting connections. Clients which weren't
+    /// handed over yet are disconnected with [`SHUTDOWN_REASON`], the others stay open, and the
+    /// receiver of connections disconnects once they all closed.
+    pub shutdown: Option<watch::Receiver<bool>>,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        return ListenerConfig {
+            address: "0.0.0.0:8080".to_string(),
+            capture_dir: None,
+            proxy_protocol: ProxyProtocol::Disabled,
+            forwarding: Forwarding::Disabled,
+            login_channels: vec![],
+            login_plugin_timeout: Duration::from_secs(10),
+            handshake_timeout: Duration::from_secs(10),
+            login_timeout: Duration::from_secs(30),
+            status_timeout: Duration::from_secs(15),
+            limits: Limits::default(),
+            shutdown: None,
+        };
+    }
+}
 
 pub fn start(rt: &mut Runtime) -> Result<Receiver<Connection>> {
+    return start_with_config(rt, ListenerConfig::default());
+}
+
+pub fn start_with_config(rt: &mut Runtime, config: ListenerConfig) -> Result<Receiver<Connection>> {
+    let listener = std::net::TcpListener::bind(&config.address)?;
+    return start_with_listener(rt, listener, config);
+}
+
+/// Like [`start_with_config`], but accepts connections on a socket which is already bound, instead
+/// of binding to [`ListenerConfig::address`].
+pub fn start_with_listener(
+    rt: &mut Runtime,
+    listener: std::net::TcpListener,
+    config: ListenerConfig,
+) -> Result<Receiver<Connection>> {
+    config.forwarding.validate()?;
+    listener.set_nonblocking(true)?;
+    info!(target: "net", "Listening on {}", listener.local_addr()?);
+    if config.forwarding == Forwarding::LegacyInsecure {
+        warn!(
+            target: "net",
+            "Legacy forwarding without BungeeGuard lets anyone who can reach {} join as any \
+            player. Make sure only the proxy can connect to it.",
+            listener.local_addr()?
+        );
+    }
+    let limiter = ConnectionLimiter::new(config.limits.clone())?;
     let (connections_sender, connections_receiver): (Sender<Connection>, Receiver<Connection>) =
         mpsc::channel();
 
@@ -52,19 +176,47 @@ pub fn start(rt: &mut Runtime) -> Result<Receiver<Connection>> {
     let public_key = RsaPublicKey::from(&private_key);
 
     let _: JoinHandle<Result<()>> = rt.spawn(async move {
-        let listener = TcpListener::bind("0.0.0.0:8080").await?;
+        let listener = TcpListener::from_std(listener)?;
+        let mut connection_count: u64 = 0;
+        let mut shutdown = config.shutdown.clone();
 
         loop {
             // Accept a connection
-            let (socket, _) = match listener.accept().await {
-                Ok(t) => t,
-                Err(_) => continue,
+            let (socket, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(t) => t,
+                    Err(_) => continue,
+                },
+                _ = shutdown_requested(&mut shutdown) => {
+                    info!(target: "net", "Stopped accepting connections.");
+                    return Ok(());
+                }
             };
+            let mut slot = match limiter.open() {
+                Ok(slot) => slot,
+                Err(e) => {
+                    warn!(target: "net", "Refused a connection from {}: {}", peer, e);
+                    continue;
+                }
+            };
+            let limiter = limiter.clone();
+            connection_count += 1;
+            let connection_id = connection_count;
+            let capture_dir = config.capture_dir.clone();
+            let proxy_protocol = config.proxy_protocol;
+            let forwarding = config.forwarding.clone();
+            let login_channels = config.login_channels.clone();
+            let login_plugin_timeout = config.login_plugin_timeout;
+            let handshake_timeout = config.handshake_timeout;
+            let login_timeout = config.login_timeout;
+            let status_timeout = config.status_timeout;
+            let shutdown = config.shutdown.clone();
 
             let (packet_sender, packet_receiver): (
                 Sender<Cursor<Vec<u8>>>,
                 Receiver<Cursor<Vec<u8>>>,
             ) = mpsc::channel();
+            let (outgoing_sender, outgoing_receiver) = unbounded_channel();
 
             // Clone the sender for this client
             let connections_sender = connections_sender.clone();
@@ -74,81 +226,178 @@ pub fn start(rt: &mut Runtime) -> Result<Receiver<Connection>> {
             let private_key = private_key.clone();
 
             let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
-                let mut socket = new_buffered_socket(socket);
+                let mut socket = new_buffered_socket(socket, limiter.clone(), shutdown);
+                let handshake_deadline = Instant::now() + handshake_timeout;
+                let address = unwrap_some_or!(
+                    within(
+                        handshake_deadline,
+                        "the PROXY header",
+                        read_proxy_header(&mut socket, peer, proxy_protocol)
+                    )
+                    .await?,
+                    return Ok(())
+                );
+                // Before reading anything else, so clients over a limit can't hold on to the
+                // connection. They can't be told why, as their protocol state isn't known yet.
+                limiter.admit(&mut slot, address.ip())?;
+                if let Some(dir) = capture_dir {
+                    socket.recorder = Some(Recorder::create(&dir, connection_id).await?);
+                }
 
                 // First, accept a handshake packet
                 let handshake: Handshake = deserializer::from_bytes(&mut unwrap_some_or!(
-                    read_packet(&mut socket).await?,
+                    within(
+                        handshake_deadline,
+                        "the handshake",
+                        read_packet(&mut socket)
+                    )
+                    .await?,
                     return Ok(())
                 ))?;
 
                 // Client wants the Status state
                 if handshake.next_state.value == 1 {
+                    socket.state = ProtocolState::Status;
                     // We can't respond with this packet as we don't have any information about
                     // the current world. Let's send this client over to the receiver.
                     connections_sender
-                        .send((ProtocolState::Status, packet_receiver))
+                        .send(Connection {
+                            state: ProtocolState::Status,
+                            packets: packet_receiver,
+                            outgoing: outgoing_sender,
+                            address,
+                            player: None,
+                        })
                         .map_err(|e| anyhow!("{e}"))?;
-                    loop {
-                        // Read a packet
-                        let packet =
-                            unwrap_some_or!(read_packet(&mut socket).await?, return Ok(()));
-
-                        // Send it across the channel
-                        packet_sender.send(packet)?;
-                    }
+                    let deadline = Instant::now() + status_timeout;
+                    return relay(
+                        &mut socket,
+                        &packet_sender,
+                        outgoing_receiver,
+                        Some(deadline),
+                    )
+                    .await;
                 } else {
                     // Client wants to login into the server
+                    socket.state = ProtocolState::Login;
 
                     // Process the Login Start request
                     let login_start: LoginStart = deserializer::from_bytes(&mut unwrap_some_or!(
-                        read_packet(&mut socket).await?,
+                        read_login_packet(&mut socket, login_timeout, "the login start").await?,
                         return Ok(())
                     ))?;
 
                     println!("Seems {} wants to login.", login_start.name);
 
-                    let verify_token = rand::random::<[u8; 4]>().to_vec();
-
-                    println!("Sending verify token {:?}", verify_token);
-
-                    // Send an encryption request
-                    let encryption_request = EncryptionRequest {
-                        server_id: String::new(),
-                        public_key: public_key.to_pkcs1_der()?.into_vec(),
-                        verify_token: verify_token,
-                    };
-                    socket
-                        .socket
-                        .write_all(&mut serializer::to_bytes(
+                    if forwarding != Forwarding::Disabled {
+                        let player = match forwarded_player(
+                            &mut socket,
+                            &forwarding,
+                            &handshake,
+                            &login_start,
+                            login_plugin_timeout,
+                        )
+                        .await
+                        {
+                            Ok(Some(player)) => player,
+                            Ok(None) => return Ok(()),
+                            Err(e) => {
+                                disconnect(&mut socket, "Unable to verify player details.").await?;
+                                return Err(e);
+                            }
+                        };
+                        if !run_login_channels(
+                            &mut socket,
+                            &login_channels,
+                            &login_start,
+                            login_plugin_timeout,
+                        )
+                        .await?
+                        {
+                            return Ok(());
+                        }
+
+                        let login_success = LoginSuccess {
+                            uuid: MinecraftUuid(player.uuid),
+                            username: player.username.clone(),
+                            properties: player.properties.clone(),
+                        };
+                        let mut out = BytesMut::new();
+                        serializer::encode_into(
+                            &login_success,
+                            login_success.packet_id(),
+                            &mut out,
+                        )?;
+                        write_packet(&mut socket, &out).await?;
+                        socket.state = ProtocolState::Play;
+
+                        connections_sender
+                            .send(Connection {
+                                state: ProtocolState::Play,
+                                packets: packet_receiver,
+                                outgoing: outgoing_sender,
+                                address: SocketAddr::new(player.address, address.port()),
+                                player: Some(player),
+                            })
+                            .map_err(|e| anyhow!("{e}"))?;
+                    } else {
+                        if !run_login_channels(
+                            &mut socket,
+                            &login_channels,
+                            &login_start,
+                            login_plugin_timeout,
+                        )
+                        .await?
+                        {
+                            return Ok(());
+                        }
+
+                        let verify_token = rand::random::<[u8; 4]>().to_vec();
+
+                        println!("Sending verify token {:?}", verify_token);
+
+                        // Send an encryption request
+                        let encryption_request = EncryptionRequest {
+                            server_id: String::new(),
+                            public_key: public_key.to_pkcs1_der()?.into_vec(),
+                            verify_token: verify_token,
+                        };
+                        let mut out = BytesMut::new();
+                        serializer::encode_into(
                             &encryption_request,
                             encryption_request.packet_id(),
-                        )?)
-                        .await?;
-
-                    println!("Sent!");
-
-                    // Process the Encryption Response packet
-                    let encryption_response: EncryptionResponse = deserializer::from_bytes(
-                        &mut unwrap_some_or!(read_packet(&mut socket).await?, return Ok(())),
-                    )?;
-
-                    println!(
-                        "Got back verify token {:?}",
-                        private_key.decrypt(
-                            PaddingScheme::new_pkcs1v15_encrypt(),
-                            &encryption_response.verify_token
-                        )?
-                    );
+                            &mut out,
+                        )?;
+                        write_packet(&mut socket, &out).await?;
+
+                        println!("Sent!");
+
+                        // Process the Encryption Response packet
+                        let encryption_response: EncryptionResponse =
+                            deserializer::from_bytes(&mut unwrap_some_or!(
+                                read_login_packet(
+                                    &mut socket,
+                                    login_timeout,
+                                    "the encryption response"
+                                )
+                                .await?,
+                                return Ok(())
+                            ))?;
+
+                        println!(
+                            "Got back verify token {:?}",
+                            private_key.decrypt(
+                                PaddingScheme::new_pkcs1v15_encrypt(),
+                                &encryption_response.verify_token
+                            )?
+                        );
+
+                        // The login goes no further, so nothing will ever be sent to the client.
+                        drop(outgoing_sender);
+                    }
                 }
 
-                loop {
-                    // Read a packet
-                    let packet = unwrap_some_or!(read_packet(&mut socket).await?, return Ok(()));
-
-                    // Send it across the channel
-                    packet_sender.send(packet)?;
-                }
+                return relay(&mut socket, &packet_sender, outgoing_receiver, None).await;
             });
 
             // Error handling thread
@@ -169,19 +418,356 @@ pub fn start(rt: &mut Runtime) -> Result<Receiver<Connection>> {
 struct BufferedSocket {
     buf: Vec<u8>,
     socket: TcpStream,
+    /// The protocol state the connection is in, as far as the listener knows.
+    state: ProtocolState,
+    recorder: Option<Recorder>,
+    /// The message id of the next login plugin request.
+    next_message_id: i32,
+    limiter: ConnectionLimiter,
+    /// What the client sent in the current second, counted against the packet rate limits.
+    rate: RateCounter,
+    /// Stops waiting for the client once the listener is asked to stop.
+    shutdown: Option<watch::Receiver<bool>>,
 }
 
-fn new_buffered_socket(socket: TcpStream) -> BufferedSocket {
+fn new_buffered_socket(
+    socket: TcpStream,
+    limiter: ConnectionLimiter,
+    shutdown: Option<watch::Receiver<bool>>,
+) -> BufferedSocket {
     return BufferedSocket {
         buf: vec![],
         socket: socket,
+        state: ProtocolState::Void,
+        recorder: None,
+        next_message_id: 0,
+        limiter,
+        rate: RateCounter::new(),
+        shutdown,
     };
 }
 
+/// Records the frames of a connection into a capture file.
+struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    async fn create(dir: &Path, connection_id: u64) -> Result<Self> {
+        tokio::fs::create_dir_all(dir).await?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let path = dir.join(format!("{started}-{connection_id}.opcap"));
+        let mut file = BufWriter::new(File::create(&path).await?);
+        let mut header = vec![];
+        capture::write_header(&mut header)?;
+        file.write_all(&header).await?;
+        file.flush().await?;
+        info!(target: "net", "Recording connection {} into {}", connection_id, path.display());
+        return Ok(Recorder { file });
+    }
+
+    async fn record(&mut self, frame: Frame) -> Result<()> {
+        let mut record = vec![];
+        frame.encode(&mut record);
+        self.file.write_all(&record).await?;
+        // Flushed after every frame, so captures of crashed connections are complete.
+        self.file.flush().await?;
+        return Ok(());
+    }
+}
+
+/// Records a frame if the socket is being recorded. A failing recording is stopped, instead of
+/// closing the connection.
+async fn record_frame(socket: &mut BufferedSocket, direction: Direction, data: &[u8]) {
+    let state = socket.state;
+    if let Some(recorder) = &mut socket.recorder {
+        let frame = Frame::now(direction, state, data.to_vec());
+        if let Err(e) = recorder.record(frame).await {
+            error!(target: "net", "Stopped recording a connection: {}", e);
+            socket.recorder = None;
+        }
+    }
+}
+
+/// Writes one or more complete frames to the socket. Frames too long for the client to read are an
+/// error.
+async fn write_packet(socket: &mut BufferedSocket, frame: &[u8]) -> Result<()> {
+    framing::check_frames(frame)?;
+    socket.socket.write_all(frame).await?;
+    record_frame(socket, Direction::Clientbound, frame).await;
+    return Ok(());
+}
+
+/// Reads the PROXY header a connection starts with, and returns the address of the client. Returns
+/// None if the connection closed first.
+async fn read_proxy_header(
+    socket: &mut BufferedSocket,
+    peer: SocketAddr,
+    mode: ProxyProtocol,
+) -> Result<Option<SocketAddr>> {
+    if mode == ProxyProtocol::Disabled {
+        return Ok(Some(peer));
+    }
+    loop {
+        match proxy_protocol::parse_header(&socket.buf)? {
+            ProxyHeader::Incomplete => {}
+            ProxyHeader::Absent if mode == ProxyProtocol::Required => {
+                return Err(anyhow!("connection from {peer} lacks a PROXY header"));
+            }
+            ProxyHeader::Absent => return Ok(Some(peer)),
+            ProxyHeader::Present { length, source } => {
+                socket.buf.drain(..length);
+                return Ok(Some(source.unwrap_or(peer)));
+            }
+        }
+        if populate_socket(socket).await?.is_none() {
+            return Ok(None);
+        }
+    }
+}
+
+/// Learns who is joining from the proxy in front of the listener. Returns None if the connection
+/// closed first.
+async fn forwarded_player(
+    socket: &mut BufferedSocket,
+    forwarding: &Forwarding,
+    handshake: &Handshake,
+    login_start: &LoginStart,
+    timeout: Duration,
+) -> Result<Option<ForwardedPlayer>> {
+    match forwarding {
+        Forwarding::Disabled => return Ok(None),
+        Forwarding::Legacy { secret } => {
+            return ForwardedPlayer::from_legacy(
+                &handshake.server_address,
+                &login_start.name,
+                Some(secret),
+            )
+            .map(Some);
+        }
+        Forwarding::LegacyInsecure => {
+            return ForwardedPlayer::from_legacy(
+                &handshake.server_address,
+                &login_start.name,
+                None,
+            )
+            .map(Some);
+        }
+        Forwarding::Modern { secret } => {
+            let requests = vec![(VELOCITY_CHANNEL.to_string(), vec![VELOCITY_VERSION])];
+            let mut responses = unwrap_some_or!(
+                login_plugin_requests(socket, requests, timeout).await?,
+                return Ok(None)
+            );
+            return match responses.remove(0) {
+                Some(data) => ForwardedPlayer::from_modern(&data, secret).map(Some),
+                None => Err(anyhow!("the client didn't connect through Velocity")),
+            };
+        }
+    }
+}
+
+/// Asks every login plugin channel to check the player. A player one of them rejects is
+/// disconnected. Returns whether the login can go on.
+async fn run_login_channels(
+    socket: &mut BufferedSocket,
+    channels: &[Arc<dyn LoginChannel>],
+    login_start: &LoginStart,
+    timeout: Duration,
+) -> Result<bool> {
+    if channels.is_empty() {
+        return Ok(true);
+    }
+    let requests = channels
+        .iter()
+        .map(|c| (c.channel().to_string(), c.request(login_start)))
+        .collect();
+    let checked = match login_plugin_requests(socket, requests, timeout).await {
+        Ok(Some(responses)) => channels
+            .iter()
+            .zip(&responses)
+            .try_for_each(|(c, data)| c.response(login_start, data.as_deref())),
+        Ok(None) => return Ok(false),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = checked {
+        disconnect(socket, &e.to_string()).await?;
+        return Err(e);
+    }
+    return Ok(true);
+}
+
+/// Sends login plugin requests of `(channel, data)`, and waits for the responses to all of them.
+/// Returns the data of the responses in the order of the requests, or None if the connection
+/// closed first.
+async fn login_plugin_requests(
+    socket: &mut BufferedSocket,
+    requests: Vec<(String, Vec<u8>)>,
+    timeout: Duration,
+) -> Result<Option<Vec<Option<Vec<u8>>>>> {
+    let first_id = socket.next_message_id;
+    let count = requests.len();
+    let mut out = BytesMut::new();
+    for (channel, data) in requests {
+        let request = LoginPluginRequest {
+            message_id: socket.next_message_id.into(),
+            channel,
+            data: Bytes(data),
+        };
+        socket.next_message_id += 1;
+        serializer::encode_into(&request, request.packet_id(), &mut out)?;
+    }
+    write_packet(socket, &out).await?;
+
+    let deadline = Instant::now() + timeout;
+    let mut responses: Vec<Option<Option<Vec<u8>>>> = vec![None; count];
+    while responses.iter().any(Option::is_none) {
+        let mut frame = match time::timeout_at(deadline, read_packet(socket)).await {
+            Ok(frame) => unwrap_some_or!(frame?, return Ok(None)),
+            Err(_) => bail!("Timed out waiting for login plugin responses."),
+        };
+        let packet: Box<dyn LoginPacket> = deserializer::from_bytes_generic(&mut frame)?;
+        let response = packet
+            .into_any()
+            .downcast::<LoginPluginResponse>()
+            .map_err(|_| anyhow!("expected a login plugin response"))?;
+        // The id comes from the client, so it may be anything.
+        let index = response
+            .message_id
+            .value
+            .checked_sub(first_id)
+            .and_then(|offset| usize::try_from(offset).ok());
+        match index.and_then(|index| responses.get_mut(index)) {
+            Some(slot @ None) => *slot = Some(response.data.map(|data| data.0)),
+            _ => bail!(
+                "unexpected login plugin response {}",
+                response.message_id.value
+            ),
+        }
+    }
+    return Ok(Some(responses.into_iter().flatten().collect()));
+}
+
+/// Sends a client in the Login or Play state away, with a plain text reason. Clients in other
+/// states can't be told why.
+async fn disconnect(socket: &mut BufferedSocket, reason: &str) -> Result<()> {
+    let reason = serde_json::json!({ "text": reason }).to_string();
+    let mut out = BytesMut::new();
+    match socket.state {
+        ProtocolState::Login => {
+            let disconnect = Disconnect { reason };
+            serializer::encode_into(&disconnect, disconnect.packet_id(), &mut out)?;
+        }
+        ProtocolState::Play => {
+            let disconnect = clientbound::Disconnect { reason };
+            serializer::encode_into(&disconnect, disconnect.packet_id(), &mut out)?;
+        }
+        ProtocolState::Void | ProtocolState::Status => return Ok(()),
+    }
+    return write_packet(socket, &out).await;
+}
+
+/// Counts a packet against the rate limit of the connection's state. Clients going over it are
+/// disconnected.
+async fn check_rate(socket: &mut BufferedSocket, length: usize) -> Result<()> {
+    let limit = unwrap_some_or!(socket.limiter.packet_rate(socket.state), return Ok(()));
+    if let Err(e) = socket.rate.count(limit, length) {
+        disconnect(socket, "You are sending too many packets.").await?;
+        return Err(e);
+    }
+    return Ok(());
+}
+
+/// Sends the packets of a client across the channel, and writes the frames sent to it, until
+/// either side closes the connection or the deadline passes.
+async fn relay(
+    socket: &mut BufferedSocket,
+    packet_sender: &Sender<Cursor<Vec<u8>>>,
+    mut outgoing: UnboundedReceiver<Vec<u8>>,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    loop {
+        while let Some(packet) = split_packet(&mut socket.buf)? {
+            record_frame(socket, Direction::Serverbound, packet.get_ref()).await;
+            check_rate(socket, packet.get_ref().len()).await?;
+            packet_sender.send(packet)?;
+        }
+        // Only reading into the buffer is raced, which loses nothing when the other side wins.
+        tokio::select! {
+            n = socket.socket.read_buf(&mut socket.buf) => {
+                if n? == 0 {
+                    return Ok(());
+                }
+            }
+            frame = outgoing.recv() => {
+                let frame = unwrap_some_or!(frame, return Ok(()));
+                write_packet(socket, &frame).await?;
+            }
+            _ = sleep_until(deadline) => bail!("the connection stayed open for too long"),
+        }
+    }
+}
+
+/// Waits until the listener is asked to stop, or forever if it can't be.
+async fn shutdown_requested(shutdown: &mut Option<watch::Receiver<bool>>) {
+    if let Some(shutdown) = shutdown {
+        while !*shutdown.borrow() {
+            if shutdown.changed().await.is_err() {
+                break;
+            }
+        }
+        if *shutdown.borrow() {
+            return;
+        }
+    }
+    return future::pending().await;
+}
+
+/// Sleeps until the deadline, or forever without one.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
+
+/// Runs a step of a connection, which fails if it isn't done by the deadline.
+async fn within<T>(
+    deadline: Instant,
+    step: &str,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    return time::timeout_at(deadline, future)
+        .await
+        .map_err(|_| anyhow!("timed out waiting for {step}"))?;
+}
+
+/// Reads the packet of a login step. Clients taking longer than the timeout are disconnected.
+async fn read_login_packet(
+    socket: &mut BufferedSocket,
+    timeout: Duration,
+    step: &str,
+) -> Result<Option<Cursor<Vec<u8>>>> {
+    match time::timeout(timeout, read_packet(socket)).await {
+        Ok(packet) => return packet,
+        Err(_) => {
+            disconnect(socket, "Took too long to log in.").await?;
+            bail!("timed out waiting for {step}");
+        }
+    }
+}
+
 /// Reads some bytes from the socket's tcp socket and
-/// populates the buffer.
+/// populates the buffer. Once the listener is asked to stop, the client is disconnected and
+/// treated as if it closed the connection.
 async fn populate_socket(socket: &mut BufferedSocket) -> Result<Option<()>> {
-    let n = socket.socket.read_buf(&mut socket.buf).await?;
+    let n = tokio::select! {
+        n = socket.socket.read_buf(&mut socket.buf) => n?,
+        _ = shutdown_requested(&mut socket.shutdown) => {
+            disconnect(socket, SHUTDOWN_REASON).await?;
+            return Ok(None);
+        }
+    };
     if n == 0 {
         return Ok(None);
     }
@@ -191,47 +777,16 @@ async fn populate_socket(socket: &mut BufferedSocket) -> Result<Option<()>> {
 /// Returns a complete packet from a socket. Returns None if
 /// the connection closed and the socket can no longer provide
 /// packets.
-#[async_recursion]
 async fn read_packet(socket: &mut BufferedSocket) -> Result<Option<Cursor<Vec<u8>>>> {
-    // Attempt reading a packet length
-    let mut reader = Cursor::new(std::mem::take(&mut socket.buf));
-    let length = match read_var_int(&mut reader) {
-        Ok(n) => n,
-        Err(_) => {
-            // Not enough data, populate
-            match populate_socket(socket).await? {
-                None => return Ok(None),
-                _ => {}
-            };
-            return read_packet(socket).await;
+    loop {
+        if let Some(packet) = split_packet(&mut socket.buf)? {
+            record_frame(socket, Direction::Serverbound, packet.get_ref()).await;
+            check_rate(socket, packet.get_ref().len()).await?;
+            return Ok(Some(packet));
         }
-    };
-    let length_data = length.value;
-    let length_tag = length.size;
-
-    // Calculations after this need packet as a vec, not a cursor
-    socket.buf = reader.into_inner();
-
-    // Check if the buffer has enough to pop packet
-    let length_data: usize = length_data.try_into()?;
-    let length_entire_packet = length_data + length_tag;
-    if length_entire_packet > socket.buf.len() || length_data == 0 {
         // Entire packet isn't buffered yet, populate
-        match populate_socket(socket).await? {
-            None => return Ok(None),
-            _ => {}
-        };
-        return read_packet(socket).await;
-    } else {
-        // An entire packet is available
-
-        // Split the buffer
-        let remaining_buf = socket.buf.split_off(length_entire_packet);
-        // Get the packet
-        let mut packet = std::mem::replace(&mut socket.buf, remaining_buf);
-        // Truncate it so the length is accurate
-        packet.truncate(length_entire_packet);
-
-        return Ok(Some(Cursor::new(packet)));
+        if populate_socket(socket).await?.is_none() {
+            return Ok(None);
+        }
     }
 }
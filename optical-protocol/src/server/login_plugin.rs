@@ -0,0 +1,27 @@
+//! Login plugin channels, through which the server talks to mods and proxies while a player logs
+//! in.
+//!
+//! Every channel in [`ListenerConfig::login_channels`](super::ListenerConfig::login_channels)
+//! sends a request to each player logging in, before the login succeeds. The requests go out
+//! together, each with its own message id, and the listener waits for the matching responses
+//! until [`ListenerConfig::login_plugin_timeout`](super::ListenerConfig::login_plugin_timeout).
+//! A player who doesn't answer in time, or whose response a channel rejects, is disconnected.
+
+use core::fmt::Debug;
+
+use anyhow::Result;
+
+use crate::packets::login::serverbound::LoginStart;
+
+/// A channel taking part in every login.
+pub trait LoginChannel: Debug + Send + Sync {
+    /// The identifier of the channel, like `velocity:player_info`.
+    fn channel(&self) -> &str;
+
+    /// The data of the request sent to a player logging in.
+    fn request(&self, login_start: &LoginStart) -> Vec<u8>;
+
+    /// Handles the response of the player. The data is None when the client doesn't understand
+    /// the channel. An error disconnects the player, with the error as the reason.
+    fn response(&self, login_start: &LoginStart, data: Option<&[u8]>) -> Result<()>;
+}
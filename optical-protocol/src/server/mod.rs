@@ -7,5 +7,9 @@
 //!
 //! [`channels`]: std::sync::mpsc
 
+pub mod forwarding;
+pub mod limits;
 mod listener;
+pub mod login_plugin;
+pub mod proxy_protocol;
 pub use listener::*;
@@ -0,0 +1,125 @@
+//! Parsing the header of the HAProxy PROXY protocol, which load balancers send before anything
+//! else to pass on the address of the client.
+//!
+//! Both the text header of version 1 and the binary header of version 2 are supported. See
+//! <https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt>.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// The longest a version 1 header may be, including its line ending.
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+/// The signature, version and command, address family, and length of the addresses.
+const V2_HEADER_LENGTH: usize = 16;
+
+/// The result of parsing the start of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyHeader {
+    /// More bytes are needed to tell.
+    Incomplete,
+    /// The connection doesn't start with a header.
+    Absent,
+    /// The connection starts with a header `length` bytes long. The source is None when the
+    /// header doesn't carry an address, like health checks of the load balancer.
+    Present {
+        length: usize,
+        source: Option<SocketAddr>,
+    },
+}
+
+/// Parses a PROXY header off the start of `buf`.
+pub fn parse_header(buf: &[u8]) -> Result<ProxyHeader> {
+    if starts_with(buf, V1_PREFIX) {
+        if buf.len() < V1_PREFIX.len() {
+            return Ok(ProxyHeader::Incomplete);
+        }
+        return parse_v1(buf);
+    }
+    if starts_with(buf, V2_SIGNATURE) {
+        if buf.len() < V2_HEADER_LENGTH {
+            return Ok(ProxyHeader::Incomplete);
+        }
+        return parse_v2(buf);
+    }
+    return Ok(ProxyHeader::Absent);
+}
+
+/// Whether `buf` and `prefix` agree for as long as both have bytes.
+fn starts_with(buf: &[u8], prefix: &[u8]) -> bool {
+    let length = buf.len().min(prefix.len());
+    return buf[..length] == prefix[..length];
+}
+
+fn parse_v1(buf: &[u8]) -> Result<ProxyHeader> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None if buf.len() >= V1_MAX_LENGTH => bail!("PROXY header is too long"),
+        None => return Ok(ProxyHeader::Incomplete),
+    };
+    if end + 2 > V1_MAX_LENGTH {
+        bail!("PROXY header is too long");
+    }
+    let line = str::from_utf8(&buf[..end])?;
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    let source = match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => None,
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, _destination, port, _destination_port] => {
+            let ip: IpAddr = source.parse()?;
+            if ip.is_ipv4() != (family == "TCP4") {
+                bail!("PROXY header address {ip} doesn't match its family {family}");
+            }
+            Some(SocketAddr::new(ip, port.parse()?))
+        }
+        _ => bail!("malformed PROXY header {line:?}"),
+    };
+    return Ok(ProxyHeader::Present {
+        length: end + 2,
+        source,
+    });
+}
+
+fn parse_v2(buf: &[u8]) -> Result<ProxyHeader> {
+    let version_command = buf[12];
+    let family = buf[13];
+    let length = V2_HEADER_LENGTH + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if version_command >> 4 != 2 {
+        bail!("unsupported PROXY header version {}", version_command >> 4);
+    }
+    if buf.len() < length {
+        return Ok(ProxyHeader::Incomplete);
+    }
+    let addresses = &buf[V2_HEADER_LENGTH..length];
+    let too_short = || anyhow!("PROXY header is too short for its addresses");
+
+    let source = match (version_command & 0x0f, family >> 4) {
+        // LOCAL connections are made by the proxy itself.
+        (0, _) => None,
+        (1, 1) => {
+            let bytes: [u8; 12] = addresses.get(..12).ok_or_else(too_short)?.try_into()?;
+            let ip = Ipv4Addr::from([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            Some(SocketAddr::new(
+                ip.into(),
+                u16::from_be_bytes([bytes[8], bytes[9]]),
+            ))
+        }
+        (1, 2) => {
+            let bytes: [u8; 36] = addresses.get(..36).ok_or_else(too_short)?.try_into()?;
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&bytes[..16])?);
+            Some(SocketAddr::new(
+                ip.into(),
+                u16::from_be_bytes([bytes[32], bytes[33]]),
+            ))
+        }
+        // Unix sockets and unspecified families don't carry an address we can use.
+        (1, _) => None,
+        (command, _) => bail!("unsupported PROXY header command {command}"),
+    };
+    return Ok(ProxyHeader::Present { length, source });
+}
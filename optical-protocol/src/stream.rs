@@ -0,0 +1,199 @@
+//! Framing packets on a TCP stream, along with the encryption and compression a connection turns
+//! on during login.
+//!
+//! Once encryption is enabled, every byte is encrypted with AES-128 in CFB8 mode, using the shared
+//! secret as both the key and the initial vector. Once compression is enabled, every frame holds
+//! the uncompressed length of its packet after its own length, and packets at least as long as
+//! the threshold are compressed with zlib. An uncompressed length of 0 marks a packet which isn't
+//! compressed.
+//!
+//! [`PacketStream`] hides both of them, so frames are always read and written as the
+//! [`serializer`] produces them.
+
+use std::io::{Cursor, Read, Write};
+
+use aes::Aes128;
+use anyhow::{anyhow, bail, Result};
+use cfb8::cipher::{generic_array::GenericArray, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::format::{
+    framing, serializer,
+    types::{read_var_int, write_var_int},
+};
+
+pub use crate::format::{framing::split_packet, types::MAX_UNCOMPRESSED_LENGTH};
+
+/// A TCP stream which reads and writes whole frames.
+pub struct PacketStream {
+    socket: TcpStream,
+    /// Decrypted bytes which don't form a whole frame yet.
+    buf: Vec<u8>,
+    cipher: Option<Cipher>,
+    compression_threshold: Option<usize>,
+}
+
+struct Cipher {
+    encryptor: cfb8::Encryptor<Aes128>,
+    decryptor: cfb8::Decryptor<Aes128>,
+}
+
+impl PacketStream {
+    pub fn new(socket: TcpStream) -> Self {
+        return PacketStream {
+            socket,
+            buf: vec![],
+            cipher: None,
+            compression_threshold: None,
+        };
+    }
+
+    pub fn socket(&self) -> &TcpStream {
+        return &self.socket;
+    }
+
+    pub fn into_socket(self) -> TcpStream {
+        return self.socket;
+    }
+
+    /// Encrypts everything written from now on, and decrypts everything read which isn't buffered
+    /// yet. The shared secret must be 16 bytes long.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) -> Result<()> {
+        let invalid = |_| anyhow!("the shared secret must be 16 bytes long");
+        let mut cipher = Cipher {
+            encryptor: cfb8::Encryptor::new_from_slices(shared_secret, shared_secret)
+                .map_err(invalid)?,
+            decryptor: cfb8::Decryptor::new_from_slices(shared_secret, shared_secret)
+                .map_err(invalid)?,
+        };
+        // The peer encrypts everything after the packet which enabled encryption, so whatever
+        // followed it is already encrypted.
+        cipher.decrypt(&mut self.buf);
+        self.cipher = Some(cipher);
+        return Ok(());
+    }
+
+    /// Compresses packets at least `threshold` bytes long. A negative threshold disables
+    /// compression, as in [`SetCompression`](crate::packets::login::clientbound::SetCompression).
+    pub fn set_compression(&mut self, threshold: i32) {
+        self.compression_threshold = usize::try_from(threshold).ok();
+    }
+
+    /// Returns the next frame, decrypted and decompressed. Returns None if the connection closed.
+    ///
+    /// This is cancel safe: if it's dropped before finishing, no bytes are lost.
+    pub async fn read_frame(&mut self) -> Result<Option<Cursor<Vec<u8>>>> {
+        loop {
+            if let Some(frame) = split_packet(&mut self.buf)? {
+                return Ok(Some(Cursor::new(self.decompress(frame.into_inner())?)));
+            }
+            // Entire frame isn't buffered yet, populate
+            let start = self.buf.len();
+            if self.socket.read_buf(&mut self.buf).await? == 0 {
+                return Ok(None);
+            }
+            if let Some(cipher) = &mut self.cipher {
+                cipher.decrypt(&mut self.buf[start..]);
+            }
+        }
+    }
+
+    /// Writes one complete frame, as produced by the [`serializer`]. Frames longer than
+    /// [`MAX_PACKET_LENGTH`](crate::format::types::MAX_PACKET_LENGTH) once compressed are an
+    /// error.
+    pub async fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        let mut out = self.compress(frame)?;
+        framing::check_frames(&out)?;
+        if let Some(cipher) = &mut self.cipher {
+            cipher.encrypt(&mut out);
+        }
+        self.socket.write_all(&out).await?;
+        return Ok(());
+    }
+
+    /// Serializes a packet and writes it.
+    pub async fn send<T: Serialize>(&mut self, packet: &T, packet_id: i32) -> Result<()> {
+        let frame = serializer::to_bytes(packet, packet_id)?;
+        return self.write_frame(&frame).await;
+    }
+
+    fn compress(&self, frame: &[u8]) -> Result<Vec<u8>> {
+        let threshold = match self.compression_threshold {
+            Some(threshold) => threshold,
+            None => return Ok(frame.to_vec()),
+        };
+        let length = read_var_int(&mut Cursor::new(frame.to_vec()))?;
+        let packet = &frame[length.size..];
+
+        let mut body = vec![];
+        if packet.len() >= threshold {
+            write_var_int(&mut body, packet.len() as i32);
+            let mut encoder = ZlibEncoder::new(body, Compression::default());
+            encoder.write_all(packet)?;
+            body = encoder.finish()?;
+        } else {
+            write_var_int(&mut body, 0);
+            body.extend_from_slice(packet);
+        }
+
+        let mut out = vec![];
+        write_var_int(&mut out, body.len() as i32);
+        out.extend(body);
+        return Ok(out);
+    }
+
+    fn decompress(&self, frame: Vec<u8>) -> Result<Vec<u8>> {
+        if self.compression_threshold.is_none() {
+            return Ok(frame);
+        }
+        let mut reader = Cursor::new(frame);
+        read_var_int(&mut reader)?;
+        let uncompressed_length = read_var_int(&mut reader)?.value;
+        let body = &reader.get_ref()[reader.position() as usize..];
+
+        let packet = if uncompressed_length == 0 {
+            body.to_vec()
+        } else {
+            if uncompressed_length < 0 || uncompressed_length as usize > MAX_UNCOMPRESSED_LENGTH {
+                bail!("uncompressed packet length of {uncompressed_length} bytes is invalid");
+            }
+            let mut packet = Vec::with_capacity(uncompressed_length as usize);
+            ZlibDecoder::new(body)
+                .take(MAX_UNCOMPRESSED_LENGTH as u64 + 1)
+                .read_to_end(&mut packet)?;
+            if packet.len() != uncompressed_length as usize {
+                bail!(
+                    "packet inflated to {} bytes, but announced {uncompressed_length}",
+                    packet.len()
+                );
+            }
+            packet
+        };
+
+        let mut out = vec![];
+        write_var_int(&mut out, packet.len() as i32);
+        out.extend(packet);
+        return Ok(out);
+    }
+}
+
+impl Cipher {
+    fn encrypt(&mut self, bytes: &mut [u8]) {
+        for byte in bytes.chunks_mut(1) {
+            self.encryptor
+                .encrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+
+    fn decrypt(&mut self, bytes: &mut [u8]) {
+        for byte in bytes.chunks_mut(1) {
+            self.decryptor
+                .decrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+}
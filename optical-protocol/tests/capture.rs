@@ -0,0 +1,129 @@
+//! Tests for capture files, and for recording them in the listener.
+
+mod common;
+
+use std::{
+    fs::{self, File},
+    io::{Cursor, Read, Write},
+    time::Duration,
+};
+
+use optical_protocol::{
+    capture::{self, CaptureReader, Frame},
+    format::{
+        serializer,
+        tags::{ClientStatusPacket, StatusPacket},
+    },
+    packets::{
+        status::{clientbound::PingResponse, serverbound::PingRequest},
+        Direction, ProtocolState,
+    },
+    server::ListenerConfig,
+};
+
+#[test]
+fn frames_roundtrip() {
+    let frames = vec![
+        Frame {
+            timestamp: Duration::from_micros(1_671_000_000_000_001),
+            direction: Direction::Serverbound,
+            state: ProtocolState::Void,
+            data: vec![1, 0],
+        },
+        Frame {
+            timestamp: Duration::from_micros(1_671_000_000_000_002),
+            direction: Direction::Clientbound,
+            state: ProtocolState::Play,
+            data: vec![],
+        },
+    ];
+    let mut file = vec![];
+    capture::write_header(&mut file).unwrap();
+    for frame in &frames {
+        frame.encode(&mut file);
+    }
+
+    let read: Vec<Frame> = CaptureReader::new(Cursor::new(&file))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(read, frames);
+
+    // A capture cut off in the middle of a frame is an error, not a shorter capture.
+    let mut reader = CaptureReader::new(Cursor::new(&file[..file.len() - 5])).unwrap();
+    assert!(reader.read_frame().unwrap().is_some());
+    assert!(reader.read_frame().is_err());
+
+    assert!(CaptureReader::new(Cursor::new(b"OPCQ\x01")).is_err());
+}
+
+#[test]
+fn listener_records_connections() {
+    let dir = std::env::temp_dir().join(format!("optical-capture-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let (_runtime, connections, address) = common::listener(ListenerConfig {
+        capture_dir: Some(dir.clone()),
+        ..Default::default()
+    });
+
+    let ping = PingRequest { payload: 42 };
+    let mut sent = common::handshake(1);
+    let ping_frame = serializer::to_bytes(&ping, ping.packet_id()).unwrap();
+    sent.extend(&ping_frame);
+
+    let mut stream = common::connect(address);
+    stream.write_all(&sent).unwrap();
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(matches!(connection.state, ProtocolState::Status));
+    assert_eq!(
+        connection
+            .packets
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .into_inner(),
+        ping_frame
+    );
+
+    // Frames sent to the connection are written to the client.
+    let pong = PingResponse { payload: 42 };
+    let pong_frame = serializer::to_bytes(&pong, pong.packet_id()).unwrap();
+    connection.outgoing.send(pong_frame.clone()).unwrap();
+    let mut received = vec![0; pong_frame.len()];
+    stream.read_exact(&mut received).unwrap();
+    assert_eq!(received, pong_frame);
+
+    // Dropping the connection closes it.
+    drop(connection);
+    assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+
+    let path = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let frames: Vec<Frame> = CaptureReader::new(File::open(path).unwrap())
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let recorded: Vec<_> = frames
+        .iter()
+        .map(|f| (f.direction, f.state, f.data.len()))
+        .collect();
+    assert_eq!(
+        recorded,
+        [
+            (
+                Direction::Serverbound,
+                ProtocolState::Void,
+                sent.len() - ping_frame.len()
+            ),
+            (
+                Direction::Serverbound,
+                ProtocolState::Status,
+                ping_frame.len()
+            ),
+            (
+                Direction::Clientbound,
+                ProtocolState::Status,
+                pong_frame.len()
+            ),
+        ]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,245 @@
+//! Tests for the client, against a server written with the same packet stream.
+
+use optical_protocol::{
+    client::Client,
+    format::{
+        deserializer,
+        tags::{ClientLoginPacket, ClientPlayPacket, ClientStatusPacket},
+        types::{Bytes, Json, MinecraftUuid, TrailingOption},
+    },
+    packets::{
+        clientbound,
+        login::{
+            clientbound::{EncryptionRequest, LoginPluginRequest, LoginSuccess, SetCompression},
+            serverbound::{EncryptionResponse, LoginPluginResponse, LoginStart},
+        },
+        serverbound,
+        status::{
+            clientbound::{PingResponse, ServerStatus, StatusResponse, StatusVersion},
+            serverbound::PingRequest,
+        },
+        void::serverbound::Handshake,
+        ProtocolState, PROTOCOL_VERSION,
+    },
+    stream::PacketStream,
+};
+use rsa::{pkcs8::EncodePublicKey, PaddingScheme, RsaPrivateKey, RsaPublicKey};
+use serde::de::DeserializeOwned;
+use tokio::net::TcpListener;
+
+/// Binds a server on a free port, and returns its address.
+async fn bind() -> (TcpListener, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    return (listener, address);
+}
+
+async fn accept(listener: &TcpListener) -> PacketStream {
+    return PacketStream::new(listener.accept().await.unwrap().0);
+}
+
+async fn read<T: DeserializeOwned>(stream: &mut PacketStream) -> T {
+    let mut frame = stream.read_frame().await.unwrap().unwrap();
+    return deserializer::from_bytes(&mut frame).unwrap();
+}
+
+#[tokio::test]
+async fn frames_survive_encryption_and_compression() {
+    let (listener, address) = bind().await;
+    let mut client = PacketStream::new(tokio::net::TcpStream::connect(&address).await.unwrap());
+    let mut server = accept(&listener).await;
+
+    let secret = [7; 16];
+    client.enable_encryption(&secret).unwrap();
+    server.enable_encryption(&secret).unwrap();
+    client.set_compression(64);
+    server.set_compression(64);
+
+    let small = PingRequest { payload: 1 };
+    let large = LoginStart {
+        name: "a".repeat(200),
+        player_uuid: TrailingOption(None),
+    };
+    client.send(&small, 1).await.unwrap();
+    client.send(&large, 0).await.unwrap();
+    assert_eq!(read::<PingRequest>(&mut server).await, small);
+    assert_eq!(read::<LoginStart>(&mut server).await, large);
+
+    server.set_compression(-1);
+    client.set_compression(-1);
+    client.send(&small, 1).await.unwrap();
+    assert_eq!(read::<PingRequest>(&mut server).await, small);
+}
+
+#[tokio::test]
+async fn length_is_checked_once_compressed() {
+    let (listener, address) = bind().await;
+    let mut client = PacketStream::new(tokio::net::TcpStream::connect(&address).await.unwrap());
+    let mut server = accept(&listener).await;
+
+    // Longer than a frame may be, but it compresses to a fraction of that.
+    let large = LoginStart {
+        name: "a".repeat(3_000_000),
+        player_uuid: TrailingOption(None),
+    };
+    assert!(client.send(&large, 0).await.is_err());
+
+    client.set_compression(256);
+    server.set_compression(256);
+    client.send(&large, 0).await.unwrap();
+    assert_eq!(read::<LoginStart>(&mut server).await, large);
+}
+
+#[tokio::test]
+async fn status_query() {
+    let (listener, address) = bind().await;
+    let server = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        let handshake: Handshake = read(&mut stream).await;
+        assert_eq!(handshake.next_state.value, 1);
+        stream.read_frame().await.unwrap().unwrap();
+        let response = StatusResponse {
+            status: Json(ServerStatus {
+                version: StatusVersion {
+                    name: "1.19.3".to_string(),
+                    protocol: PROTOCOL_VERSION,
+                },
+                players: None,
+                description: None,
+                favicon: None,
+                enforces_secure_chat: None,
+            }),
+        };
+        stream.send(&response, response.packet_id()).await.unwrap();
+        let ping: PingRequest = read(&mut stream).await;
+        let pong = PingResponse {
+            payload: ping.payload,
+        };
+        stream.send(&pong, pong.packet_id()).await.unwrap();
+    });
+
+    let client = Client::connect(&address).await.unwrap();
+    let (status, _) = client.status().await.unwrap();
+    assert_eq!(status.version.protocol, PROTOCOL_VERSION);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn encrypted_compressed_login() {
+    let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = RsaPublicKey::from(&private_key);
+    let (listener, address) = bind().await;
+    let server = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        let handshake: Handshake = read(&mut stream).await;
+        assert_eq!(handshake.next_state.value, 2);
+        let login_start: LoginStart = read(&mut stream).await;
+
+        let request = EncryptionRequest {
+            server_id: String::new(),
+            public_key: public_key.to_public_key_der().unwrap().as_ref().to_vec(),
+            verify_token: vec![1, 2, 3, 4],
+        };
+        stream.send(&request, request.packet_id()).await.unwrap();
+        let response: EncryptionResponse = read(&mut stream).await;
+        let decrypt = |bytes: &[u8]| {
+            private_key
+                .decrypt(PaddingScheme::new_pkcs1v15_encrypt(), bytes)
+                .unwrap()
+        };
+        assert_eq!(decrypt(&response.verify_token), request.verify_token);
+        stream
+            .enable_encryption(&decrypt(&response.shared_secret))
+            .unwrap();
+
+        let compression = SetCompression {
+            threshold: 16.into(),
+        };
+        stream
+            .send(&compression, compression.packet_id())
+            .await
+            .unwrap();
+        stream.set_compression(16);
+        let success = LoginSuccess {
+            uuid: MinecraftUuid(uuid::Uuid::nil()),
+            username: login_start.name,
+            properties: vec![],
+        };
+        stream.send(&success, success.packet_id()).await.unwrap();
+
+        // A play packet without a definition, which the client skips.
+        stream.write_frame(&[2, 0x7f, 0]).await.unwrap();
+    });
+
+    let mut client = Client::connect(&address).await.unwrap();
+    let success = client.login("Bot").await.unwrap();
+    assert_eq!(success.username, "Bot");
+    assert_eq!(client.state(), ProtocolState::Play);
+    assert!(client.recv().await.unwrap().is_none());
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn login_plugin_requests_are_not_understood() {
+    let (listener, address) = bind().await;
+    let server = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        let _: Handshake = read(&mut stream).await;
+        let login_start: LoginStart = read(&mut stream).await;
+
+        let request = LoginPluginRequest {
+            message_id: 7.into(),
+            channel: "example:version".to_string(),
+            data: Bytes(vec![1]),
+        };
+        stream.send(&request, request.packet_id()).await.unwrap();
+        let response: LoginPluginResponse = read(&mut stream).await;
+        assert_eq!(response.message_id.value, 7);
+        assert_eq!(response.data, None);
+
+        let success = LoginSuccess {
+            uuid: MinecraftUuid(uuid::Uuid::nil()),
+            username: login_start.name,
+            properties: vec![],
+        };
+        stream.send(&success, success.packet_id()).await.unwrap();
+    });
+
+    let mut client = Client::connect(&address).await.unwrap();
+    client.login("Bot").await.unwrap();
+    assert_eq!(client.state(), ProtocolState::Play);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn keep_alives_are_answered_over_ipv6() {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        let handshake: Handshake = read(&mut stream).await;
+        assert_eq!(handshake.server_address, "::1");
+        let login_start: LoginStart = read(&mut stream).await;
+        let success = LoginSuccess {
+            uuid: MinecraftUuid(uuid::Uuid::nil()),
+            username: login_start.name,
+            properties: vec![],
+        };
+        stream.send(&success, success.packet_id()).await.unwrap();
+
+        let keep_alive = clientbound::KeepAlive { id: 5 };
+        stream
+            .send(&keep_alive, keep_alive.packet_id())
+            .await
+            .unwrap();
+        let answer: serverbound::KeepAlive = read(&mut stream).await;
+        assert_eq!(answer.id, 5);
+    });
+
+    let mut client = Client::connect(&address).await.unwrap();
+    client.login("Bot").await.unwrap();
+    let packet = client.recv().await.unwrap().unwrap();
+    assert!(packet.as_any().is::<clientbound::KeepAlive>());
+    assert!(client.recv().await.unwrap().is_none());
+    server.await.unwrap();
+}
@@ -0,0 +1,94 @@
+//! The listener fixture shared by the tests of the listener.
+
+#![allow(dead_code)]
+
+use std::{
+    any::Any,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::Receiver,
+    time::Duration,
+};
+
+use optical_protocol::{
+    format::{
+        deserializer, serializer,
+        tags::{ClientLoginPacket, LoginPacket, VoidPacket},
+        types::TrailingOption,
+    },
+    packets::{login::serverbound::LoginStart, void::serverbound::Handshake},
+    server::{self, split_packet, Connection, ListenerConfig},
+};
+use tokio::runtime::{Builder, Runtime};
+
+/// Starts a listener with `config` on a free port of localhost, and returns its address.
+pub fn listener(config: ListenerConfig) -> (Runtime, Receiver<Connection>, SocketAddr) {
+    let socket = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+    let mut runtime = Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+    let connections = server::start_with_listener(&mut runtime, socket, config).unwrap();
+    return (runtime, connections, address);
+}
+
+/// Connects to the listener. Reads time out, so a listener which never answers fails the test.
+pub fn connect(address: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    return stream;
+}
+
+pub fn handshake(next_state: i32) -> Vec<u8> {
+    let packet = Handshake {
+        protocol_version: 761.into(),
+        server_address: "localhost".to_string(),
+        server_port: 25565,
+        next_state: next_state.into(),
+    };
+    return serializer::to_bytes(&packet, packet.packet_id()).unwrap();
+}
+
+/// The handshake and login start of a player logging in.
+pub fn login(name: &str) -> Vec<u8> {
+    let login_start = LoginStart {
+        name: name.to_string(),
+        player_uuid: TrailingOption(Some(None)),
+    };
+    let mut sent = handshake(2);
+    sent.extend(serializer::to_bytes(&login_start, login_start.packet_id()).unwrap());
+    return sent;
+}
+
+/// Reads the next login packet the listener sends, which must be a `T`.
+pub fn read<T: Any>(stream: &mut TcpStream, buf: &mut Vec<u8>) -> T {
+    loop {
+        if let Some(mut frame) = split_packet(buf).unwrap() {
+            let packet: Box<dyn ClientLoginPacket> =
+                deserializer::from_bytes_generic(&mut frame).unwrap();
+            return *packet.into_any().downcast().unwrap();
+        }
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).unwrap();
+        assert_ne!(n, 0, "the listener closed the connection");
+        buf.extend(&chunk[..n]);
+    }
+}
+
+/// Reads until the listener closes the connection, and returns everything it sent.
+pub fn read_until_closed(stream: &mut TcpStream) -> Vec<u8> {
+    let mut received = vec![];
+    stream.read_to_end(&mut received).unwrap();
+    return received;
+}
+
+/// Sends a serverbound login packet.
+pub fn send<T: LoginPacket + serde::Serialize>(stream: &mut TcpStream, packet: &T) {
+    stream
+        .write_all(&serializer::to_bytes(packet, packet.packet_id()).unwrap())
+        .unwrap();
+}
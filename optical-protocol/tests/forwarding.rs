@@ -0,0 +1,216 @@
+//! Tests for player info forwarding, and for the listener's logins behind a proxy.
+
+mod common;
+
+use std::{io::Write, net::TcpStream, sync::mpsc::Receiver, time::Duration};
+
+use common::read;
+
+use optical_protocol::{
+    format::{
+        serializer,
+        tags::{LoginPacket, VoidPacket},
+        types::{Bytes, TrailingOption},
+    },
+    packets::{
+        login::{
+            clientbound::{Disconnect, LoginPluginRequest, LoginSuccess, LoginSuccessProperty},
+            serverbound::{LoginPluginResponse, LoginStart},
+        },
+        void::serverbound::Handshake,
+        ProtocolState,
+    },
+    server::{
+        self,
+        forwarding::{ForwardedPlayer, Forwarding, VELOCITY_CHANNEL},
+        Connection, ListenerConfig,
+    },
+};
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+const SECRET: &str = "hunter2";
+
+fn player() -> ForwardedPlayer {
+    return ForwardedPlayer {
+        address: "192.0.2.1".parse().unwrap(),
+        uuid: Uuid::from_u128(0x069a79f444e94726a5befca90e38aaf5),
+        username: "Notch".to_string(),
+        properties: vec![LoginSuccessProperty {
+            name: "textures".to_string(),
+            value: "e30=".to_string(),
+            signature: Some("c2lnbmVk".to_string()),
+        }],
+    };
+}
+
+#[test]
+fn legacy_forwarding() {
+    let address = "localhost\x00192.0.2.1\x00069a79f444e94726a5befca90e38aaf5\x00\
+        [{\"name\":\"textures\",\"value\":\"e30=\",\"signature\":\"c2lnbmVk\"}]";
+    assert_eq!(
+        ForwardedPlayer::from_legacy(address, "Notch", None).unwrap(),
+        player()
+    );
+    let without_properties = "localhost\x00192.0.2.1\x00069a79f444e94726a5befca90e38aaf5";
+    assert!(
+        ForwardedPlayer::from_legacy(without_properties, "Notch", None)
+            .unwrap()
+            .properties
+            .is_empty()
+    );
+    assert!(ForwardedPlayer::from_legacy("localhost", "Notch", None).is_err());
+
+    // BungeeGuard tokens are checked, and left out of the properties.
+    let guarded = player().to_legacy("localhost", Some(SECRET)).unwrap();
+    assert_eq!(
+        ForwardedPlayer::from_legacy(&guarded, "Notch", Some(SECRET)).unwrap(),
+        player()
+    );
+    assert!(ForwardedPlayer::from_legacy(&guarded, "Notch", Some("hunter3")).is_err());
+    assert!(ForwardedPlayer::from_legacy(address, "Notch", Some(SECRET)).is_err());
+}
+
+#[test]
+fn modern_forwarding() {
+    let data = player().to_modern(SECRET).unwrap();
+    assert_eq!(
+        ForwardedPlayer::from_modern(&data, SECRET).unwrap(),
+        player()
+    );
+    assert!(ForwardedPlayer::from_modern(&data, "hunter3").is_err());
+
+    let mut tampered = data.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(ForwardedPlayer::from_modern(&tampered, SECRET).is_err());
+    assert!(ForwardedPlayer::from_modern(&data[..31], SECRET).is_err());
+}
+
+fn listener(forwarding: Forwarding) -> (Runtime, Receiver<Connection>, TcpStream) {
+    let (runtime, connections, address) = common::listener(ListenerConfig {
+        forwarding,
+        ..Default::default()
+    });
+    return (runtime, connections, common::connect(address));
+}
+
+fn login(stream: &mut TcpStream, server_address: String) {
+    let handshake = Handshake {
+        protocol_version: 761.into(),
+        server_address,
+        server_port: 25565,
+        next_state: 2.into(),
+    };
+    let login_start = LoginStart {
+        name: "Notch".to_string(),
+        player_uuid: TrailingOption(Some(None)),
+    };
+    let mut sent = serializer::to_bytes(&handshake, handshake.packet_id()).unwrap();
+    sent.extend(serializer::to_bytes(&login_start, login_start.packet_id()).unwrap());
+    stream.write_all(&sent).unwrap();
+}
+
+#[test]
+fn listener_with_legacy_forwarding() {
+    let (_runtime, connections, mut stream) = listener(Forwarding::Legacy {
+        secret: SECRET.to_string(),
+    });
+    login(
+        &mut stream,
+        player().to_legacy("localhost", Some(SECRET)).unwrap(),
+    );
+
+    let success: LoginSuccess = read(&mut stream, &mut vec![]);
+    assert_eq!(success.uuid.0, player().uuid);
+    assert_eq!(success.properties, player().properties);
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(connection.state, ProtocolState::Play);
+    assert_eq!(connection.address.ip(), player().address);
+    assert_eq!(connection.player, Some(player()));
+}
+
+#[test]
+fn listener_rejects_unguarded_legacy_forwarding() {
+    let (_runtime, connections, mut stream) = listener(Forwarding::Legacy {
+        secret: SECRET.to_string(),
+    });
+    login(&mut stream, player().to_legacy("localhost", None).unwrap());
+    let _: Disconnect = read(&mut stream, &mut vec![]);
+    assert!(connections
+        .recv_timeout(Duration::from_millis(200))
+        .is_err());
+}
+
+#[test]
+fn listener_with_insecure_legacy_forwarding() {
+    let (_runtime, connections, mut stream) = listener(Forwarding::LegacyInsecure);
+    login(&mut stream, player().to_legacy("localhost", None).unwrap());
+    let success: LoginSuccess = read(&mut stream, &mut vec![]);
+    assert_eq!(success.uuid.0, player().uuid);
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(connection.player, Some(player()));
+}
+
+#[test]
+fn listener_with_modern_forwarding() {
+    let (_runtime, connections, mut stream) = listener(Forwarding::Modern {
+        secret: SECRET.to_string(),
+    });
+    login(&mut stream, "localhost".to_string());
+
+    let mut buf = vec![];
+    let request: LoginPluginRequest = read(&mut stream, &mut buf);
+    assert_eq!(request.channel, VELOCITY_CHANNEL);
+    let response = LoginPluginResponse {
+        message_id: request.message_id,
+        data: Some(Bytes(player().to_modern(SECRET).unwrap())),
+    };
+    common::send(&mut stream, &response);
+
+    let success: LoginSuccess = read(&mut stream, &mut buf);
+    assert_eq!(success.username, "Notch");
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(connection.player, Some(player()));
+}
+
+#[test]
+fn listener_rejects_unsigned_modern_forwarding() {
+    let (_runtime, connections, mut stream) = listener(Forwarding::Modern {
+        secret: SECRET.to_string(),
+    });
+    login(&mut stream, "localhost".to_string());
+
+    let mut buf = vec![];
+    let request: LoginPluginRequest = read(&mut stream, &mut buf);
+    // A client connecting directly doesn't understand the channel.
+    let response = LoginPluginResponse {
+        message_id: request.message_id,
+        data: None,
+    };
+    common::send(&mut stream, &response);
+
+    let _: Disconnect = read(&mut stream, &mut buf);
+    assert!(connections
+        .recv_timeout(Duration::from_millis(200))
+        .is_err());
+}
+
+#[test]
+fn listener_rejects_empty_secrets() {
+    for forwarding in [
+        Forwarding::Legacy {
+            secret: String::new(),
+        },
+        Forwarding::Modern {
+            secret: String::new(),
+        },
+    ] {
+        let socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut runtime = Runtime::new().unwrap();
+        let config = ListenerConfig {
+            forwarding,
+            ..Default::default()
+        };
+        assert!(server::start_with_listener(&mut runtime, socket, config).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+//! Tests for decoding packets of any type, and following the protocol state through them.
+
+use std::io::Cursor;
+
+use optical_protocol::{
+    format::{
+        serializer,
+        tags::{ClientLoginPacket, VoidPacket},
+        types::MinecraftUuid,
+    },
+    inspect::{self, Packet, StateTracker},
+    packets::{
+        login::clientbound::LoginSuccess, void::serverbound::Handshake, Direction, ProtocolState,
+    },
+};
+
+fn handshake(next_state: i32) -> Vec<u8> {
+    let packet = Handshake {
+        protocol_version: 761.into(),
+        server_address: "localhost".to_string(),
+        server_port: 25565,
+        next_state: next_state.into(),
+    };
+    return serializer::to_bytes(&packet, packet.packet_id()).unwrap();
+}
+
+#[test]
+fn state_follows_handshake_and_login() {
+    let mut tracker = StateTracker::default();
+    let packet = inspect::decode(
+        tracker.state,
+        Direction::Serverbound,
+        &mut Cursor::new(handshake(2)),
+    )
+    .unwrap();
+    assert!(matches!(packet, Packet::Void(_)));
+    tracker.observe(&packet);
+    assert_eq!(tracker.state, ProtocolState::Login);
+
+    let login_success = LoginSuccess {
+        uuid: MinecraftUuid(uuid::Uuid::nil()),
+        username: "Notch".to_string(),
+        properties: vec![],
+    };
+    let bytes = serializer::to_bytes(&login_success, login_success.packet_id()).unwrap();
+    let packet = inspect::decode(
+        tracker.state,
+        Direction::Clientbound,
+        &mut Cursor::new(bytes),
+    )
+    .unwrap();
+    assert_eq!(packet.packet_id(), 2);
+    tracker.observe(&packet);
+    assert_eq!(tracker.state, ProtocolState::Play);
+}
+
+#[test]
+fn status_handshake() {
+    let mut tracker = StateTracker::default();
+    let packet = inspect::decode(
+        tracker.state,
+        Direction::Serverbound,
+        &mut Cursor::new(handshake(1)),
+    )
+    .unwrap();
+    tracker.observe(&packet);
+    assert_eq!(tracker.state, ProtocolState::Status);
+    assert_eq!(
+        serde_json::to_value(&packet).unwrap()["adjacent"]["next_state"],
+        1
+    );
+}
+
+#[test]
+fn frames_as_hex() {
+    assert_eq!(inspect::hex(&handshake(1)[..4]), "1000f905");
+    assert_eq!(inspect::hex(&[]), "");
+}
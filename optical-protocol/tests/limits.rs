@@ -0,0 +1,167 @@
+//! Tests for the connection and packet rate limits of the listener.
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::mpsc::Receiver,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{connect, handshake, login};
+
+use optical_protocol::{
+    format::{
+        deserializer, serializer,
+        tags::{ClientLoginPacket, StatusPacket},
+    },
+    packets::{login::clientbound::Disconnect, status::serverbound::PingRequest, ProtocolState},
+    server::{
+        self,
+        limits::{Limits, RateLimit},
+        split_packet, Connection, ListenerConfig,
+    },
+};
+use tokio::runtime::{Builder, Runtime};
+
+fn listener(limits: Limits) -> (Runtime, Receiver<Connection>, SocketAddr) {
+    return common::listener(ListenerConfig {
+        limits,
+        ..Default::default()
+    });
+}
+
+/// Reads until the listener closes the connection, and returns the reason it gave, if any.
+fn disconnect_reason(stream: &mut TcpStream) -> Option<String> {
+    let mut received = vec![];
+    let _ = stream.read_to_end(&mut received);
+    let mut reason = None;
+    while let Some(mut frame) = split_packet(&mut received).unwrap() {
+        let packet: Box<dyn ClientLoginPacket> =
+            deserializer::from_bytes_generic(&mut frame).unwrap();
+        if let Some(disconnect) = packet.as_any().downcast_ref::<Disconnect>() {
+            reason = Some(disconnect.reason.clone());
+        }
+    }
+    return reason;
+}
+
+#[test]
+fn global_cap() {
+    let (_runtime, connections, address) = listener(Limits {
+        max_connections: Some(1),
+        ..Default::default()
+    });
+    let mut first = connect(address);
+    first.write_all(&handshake(1)).unwrap();
+    let _connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    let mut second = connect(address);
+    assert_eq!(disconnect_reason(&mut second), None);
+
+    // Closing the first connection makes room.
+    drop(first);
+    thread::sleep(Duration::from_millis(100));
+    let mut third = connect(address);
+    third.write_all(&handshake(1)).unwrap();
+    assert!(connections.recv_timeout(Duration::from_secs(5)).is_ok());
+}
+
+#[test]
+fn concurrent_connections_per_ip() {
+    let (_runtime, connections, address) = listener(Limits {
+        concurrent_per_ip: Some(1),
+        ..Default::default()
+    });
+    let mut first = connect(address);
+    first.write_all(&handshake(1)).unwrap();
+    let _connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    // The second connection is closed before it gets to send the handshake.
+    let started = Instant::now();
+    let mut second = connect(address);
+    assert_eq!(disconnect_reason(&mut second), None);
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(connections.try_recv().is_err());
+}
+
+#[test]
+fn connection_rate_per_ip() {
+    let (_runtime, connections, address) = listener(Limits {
+        connections_per_ip: Some(2),
+        connection_window: Duration::from_secs(60),
+        ..Default::default()
+    });
+    for _ in 0..2 {
+        let mut stream = connect(address);
+        stream.write_all(&handshake(1)).unwrap();
+        connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    // Closed connections still count within the window.
+    let mut stream = connect(address);
+    stream.write_all(&handshake(1)).unwrap();
+    assert_eq!(disconnect_reason(&mut stream), None);
+    assert!(connections.try_recv().is_err());
+}
+
+#[test]
+fn packet_rates() {
+    let (_runtime, connections, address) = listener(Limits {
+        packet_rates: HashMap::from([
+            (
+                ProtocolState::Status,
+                RateLimit {
+                    packets_per_second: 2,
+                    bytes_per_second: 1024,
+                },
+            ),
+            (
+                ProtocolState::Login,
+                RateLimit {
+                    packets_per_second: 10,
+                    bytes_per_second: 16,
+                },
+            ),
+        ]),
+        ..Default::default()
+    });
+
+    let mut stream = connect(address);
+    let ping = PingRequest { payload: 0 };
+    let mut sent = handshake(1);
+    for _ in 0..3 {
+        sent.extend(serializer::to_bytes(&ping, ping.packet_id()).unwrap());
+    }
+    stream.write_all(&sent).unwrap();
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(disconnect_reason(&mut stream), None);
+    assert_eq!(connection.packets.iter().count(), 2);
+
+    // Too many bytes at once.
+    let mut stream = connect(address);
+    stream.write_all(&login("a_very_long_name")).unwrap();
+    let reason = disconnect_reason(&mut stream).unwrap();
+    assert!(reason.contains("too many packets"));
+}
+
+#[test]
+fn connection_rate_needs_a_window() {
+    assert!(!Limits::default().connection_window.is_zero());
+
+    let limits = Limits {
+        connections_per_ip: Some(2),
+        connection_window: Duration::ZERO,
+        ..Default::default()
+    };
+    let mut runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = ListenerConfig {
+        limits,
+        ..Default::default()
+    };
+    assert!(server::start_with_listener(&mut runtime, socket, config).is_err());
+}
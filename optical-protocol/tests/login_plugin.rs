@@ -0,0 +1,154 @@
+//! Tests for login plugin channels in the listener.
+
+mod common;
+
+use std::{
+    io::Write,
+    net::TcpStream,
+    sync::{mpsc::Receiver, Arc},
+    time::Duration,
+};
+
+use common::read;
+
+use anyhow::{bail, Result};
+use optical_protocol::{
+    format::types::Bytes,
+    packets::login::{
+        clientbound::{Disconnect, EncryptionRequest, LoginPluginRequest},
+        serverbound::{LoginPluginResponse, LoginStart},
+    },
+    server::{login_plugin::LoginChannel, Connection, ListenerConfig},
+};
+use tokio::runtime::Runtime;
+
+/// Asks players for their mod version, and only lets version 2 in.
+#[derive(Debug)]
+struct ModVersion;
+
+impl LoginChannel for ModVersion {
+    fn channel(&self) -> &str {
+        return "example:version";
+    }
+
+    fn request(&self, login_start: &LoginStart) -> Vec<u8> {
+        return login_start.name.as_bytes().to_vec();
+    }
+
+    fn response(&self, _login_start: &LoginStart, data: Option<&[u8]>) -> Result<()> {
+        match data {
+            Some([2]) => return Ok(()),
+            Some(_) => bail!("Please update the mod."),
+            None => bail!("Please install the mod."),
+        }
+    }
+}
+
+/// Lets everyone in, and only checks the request was answered.
+#[derive(Debug)]
+struct Optional;
+
+impl LoginChannel for Optional {
+    fn channel(&self) -> &str {
+        return "example:optional";
+    }
+
+    fn request(&self, _login_start: &LoginStart) -> Vec<u8> {
+        return vec![];
+    }
+
+    fn response(&self, _login_start: &LoginStart, _data: Option<&[u8]>) -> Result<()> {
+        return Ok(());
+    }
+}
+
+fn listener(timeout: Duration) -> (Runtime, Receiver<Connection>, TcpStream) {
+    let (runtime, connections, address) = common::listener(ListenerConfig {
+        login_channels: vec![Arc::new(ModVersion), Arc::new(Optional)],
+        login_plugin_timeout: timeout,
+        ..Default::default()
+    });
+    let mut stream = common::connect(address);
+    stream.write_all(&common::login("Notch")).unwrap();
+    return (runtime, connections, stream);
+}
+
+fn respond(stream: &mut TcpStream, request: &LoginPluginRequest, data: Option<Vec<u8>>) {
+    let response = LoginPluginResponse {
+        message_id: request.message_id.value.into(),
+        data: data.map(Bytes),
+    };
+    common::send(stream, &response);
+}
+
+#[test]
+fn channels_let_players_in() {
+    let (_runtime, _connections, mut stream) = listener(Duration::from_secs(5));
+    let mut buf = vec![];
+    let version: LoginPluginRequest = read(&mut stream, &mut buf);
+    assert_eq!(version.channel, "example:version");
+    assert_eq!(version.data.0, b"Notch");
+    let optional: LoginPluginRequest = read(&mut stream, &mut buf);
+    assert_eq!(optional.channel, "example:optional");
+    assert_ne!(version.message_id, optional.message_id);
+
+    // Responses may come in any order.
+    respond(&mut stream, &optional, None);
+    respond(&mut stream, &version, Some(vec![2]));
+    let _: EncryptionRequest = read(&mut stream, &mut buf);
+}
+
+#[test]
+fn channels_reject_players() {
+    let (_runtime, _connections, mut stream) = listener(Duration::from_secs(5));
+    let mut buf = vec![];
+    let version: LoginPluginRequest = read(&mut stream, &mut buf);
+    let optional: LoginPluginRequest = read(&mut stream, &mut buf);
+    respond(&mut stream, &version, None);
+    respond(&mut stream, &optional, None);
+
+    let disconnect: Disconnect = read(&mut stream, &mut buf);
+    assert!(disconnect.reason.contains("Please install the mod."));
+}
+
+#[test]
+fn unknown_message_ids_are_rejected() {
+    let (_runtime, _connections, mut stream) = listener(Duration::from_secs(5));
+    let mut buf = vec![];
+    let version: LoginPluginRequest = read(&mut stream, &mut buf);
+    let _: LoginPluginRequest = read(&mut stream, &mut buf);
+    respond(&mut stream, &version, Some(vec![2]));
+    respond(&mut stream, &version, Some(vec![2]));
+
+    let _: Disconnect = read(&mut stream, &mut buf);
+}
+
+#[test]
+fn out_of_range_message_ids_are_rejected() {
+    for message_id in [-1, i32::MIN, i32::MAX, 2] {
+        let (_runtime, _connections, mut stream) = listener(Duration::from_secs(5));
+        let mut buf = vec![];
+        let _: LoginPluginRequest = read(&mut stream, &mut buf);
+        let _: LoginPluginRequest = read(&mut stream, &mut buf);
+        let response = LoginPluginResponse {
+            message_id: message_id.into(),
+            data: None,
+        };
+        common::send(&mut stream, &response);
+
+        let disconnect: Disconnect = read(&mut stream, &mut buf);
+        assert!(disconnect.reason.contains("unexpected login plugin response"));
+    }
+}
+
+#[test]
+fn unanswered_requests_time_out() {
+    let (_runtime, _connections, mut stream) = listener(Duration::from_millis(200));
+    let mut buf = vec![];
+    let version: LoginPluginRequest = read(&mut stream, &mut buf);
+    let _: LoginPluginRequest = read(&mut stream, &mut buf);
+    respond(&mut stream, &version, Some(vec![2]));
+
+    let disconnect: Disconnect = read(&mut stream, &mut buf);
+    assert!(disconnect.reason.contains("Timed out"));
+}
@@ -0,0 +1,76 @@
+//! Tests asserting that hostile input is rejected with an error instead of a panic.
+
+use std::io::Cursor;
+
+use optical_protocol::{
+    format::{deserializer, error::Error, tags::ClientLoginPacket},
+    packets::{GameMode, Hand},
+    server::split_packet,
+};
+
+#[test]
+fn unknown_enum_discriminant() {
+    let res: Result<GameMode, Error> = deserializer::from_bytes(&mut Cursor::new(vec![2, 0, 42]));
+    assert!(matches!(res, Err(Error::UnknownDiscriminant(42))));
+    let res: Result<Hand, Error> = deserializer::from_bytes(&mut Cursor::new(vec![2, 0, 2]));
+    assert!(matches!(res, Err(Error::UnknownDiscriminant(2))));
+}
+
+#[test]
+fn overlong_var_int() {
+    let res: Result<Box<dyn ClientLoginPacket>, Error> =
+        deserializer::from_bytes_generic(&mut Cursor::new(vec![0xff; 8]));
+    assert!(matches!(res, Err(Error::MalformedVarInt)));
+}
+
+#[test]
+fn invalid_packet_lengths() {
+    assert!(matches!(
+        split_packet(&mut vec![0, 0]),
+        Err(Error::InvalidPacketLength(0))
+    ));
+    assert!(matches!(
+        split_packet(&mut vec![0xff, 0xff, 0xff, 0xff, 0x0f]),
+        Err(Error::InvalidPacketLength(-1))
+    ));
+    assert!(matches!(
+        split_packet(&mut vec![0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+        Err(Error::MalformedVarInt)
+    ));
+}
+
+#[test]
+fn split_pipelined_packets() {
+    let mut buf = vec![1, 0, 2, 1, 5, 3];
+    assert_eq!(
+        split_packet(&mut buf).unwrap().unwrap().into_inner(),
+        [1, 0]
+    );
+    assert_eq!(
+        split_packet(&mut buf).unwrap().unwrap().into_inner(),
+        [2, 1, 5]
+    );
+    assert!(split_packet(&mut buf).unwrap().is_none());
+    assert_eq!(buf, [3]);
+}
+
+#[test]
+fn strings_must_be_utf8() {
+    let res: Result<String, Error> =
+        deserializer::from_bytes(&mut Cursor::new(vec![4, 0, 2, 0xc3, 0x28]));
+    assert!(matches!(res, Err(Error::MalformedString)));
+    // The length is in bytes, so a string can't end in the middle of a character.
+    let res: Result<String, Error> =
+        deserializer::from_bytes(&mut Cursor::new(vec![3, 0, 1, 0xc3]));
+    assert!(matches!(res, Err(Error::MalformedString)));
+    let res: Result<String, Error> =
+        deserializer::from_bytes(&mut Cursor::new(vec![3, 0, 5, b'a']));
+    assert!(matches!(res, Err(Error::MalformedString)));
+}
+
+#[test]
+fn negative_sequence_length() {
+    let res: Result<Vec<u8>, Error> =
+        deserializer::from_bytes(&mut Cursor::new(vec![6, 0, 0xff, 0xff, 0xff, 0xff, 0x0f]));
+    assert!(matches!(res, Err(Error::MalformedVarInt)));
+}
@@ -0,0 +1,113 @@
+//! Tests for the proxy binary, between the client and a server written with the packet stream.
+
+use std::{
+    net::TcpListener as StdTcpListener,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use optical_protocol::{
+    client::Client,
+    format::{deserializer, tags::ClientLoginPacket, types::MinecraftUuid},
+    packets::{
+        login::{
+            clientbound::{EncryptionRequest, LoginSuccess, SetCompression},
+            serverbound::{EncryptionResponse, LoginStart},
+        },
+        void::serverbound::Handshake,
+    },
+    stream::PacketStream,
+};
+use rsa::{pkcs8::EncodePublicKey, PaddingScheme, RsaPrivateKey, RsaPublicKey};
+use serde::de::DeserializeOwned;
+use tokio::net::TcpListener;
+
+async fn read<T: DeserializeOwned>(stream: &mut PacketStream) -> T {
+    let mut frame = stream.read_frame().await.unwrap().unwrap();
+    return deserializer::from_bytes(&mut frame).unwrap();
+}
+
+#[tokio::test]
+async fn encrypted_compressed_login_through_proxy() {
+    let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = RsaPublicKey::from(&private_key);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream = listener.local_addr().unwrap().to_string();
+
+    let server = tokio::spawn(async move {
+        let mut stream = PacketStream::new(listener.accept().await.unwrap().0);
+        let handshake: Handshake = read(&mut stream).await;
+        assert_eq!(handshake.next_state.value, 2);
+        let login_start: LoginStart = read(&mut stream).await;
+
+        let request = EncryptionRequest {
+            server_id: String::new(),
+            public_key: public_key.to_public_key_der().unwrap().as_ref().to_vec(),
+            verify_token: vec![4, 3, 2, 1],
+        };
+        stream.send(&request, request.packet_id()).await.unwrap();
+        let response: EncryptionResponse = read(&mut stream).await;
+        let decrypt = |bytes: &[u8]| {
+            private_key
+                .decrypt(PaddingScheme::new_pkcs1v15_encrypt(), bytes)
+                .unwrap()
+        };
+        assert_eq!(decrypt(&response.verify_token), request.verify_token);
+        stream
+            .enable_encryption(&decrypt(&response.shared_secret))
+            .unwrap();
+
+        let compression = SetCompression {
+            threshold: 8.into(),
+        };
+        stream
+            .send(&compression, compression.packet_id())
+            .await
+            .unwrap();
+        stream.set_compression(8);
+        let success = LoginSuccess {
+            uuid: MinecraftUuid(uuid::Uuid::nil()),
+            username: login_start.name,
+            properties: vec![],
+        };
+        stream.send(&success, success.packet_id()).await.unwrap();
+        stream.write_frame(&[2, 0x7f, 0]).await.unwrap();
+    });
+
+    // Find a free port for the proxy.
+    let address = StdTcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let mut proxy = Command::new(env!("CARGO_BIN_EXE_optical-proxy"))
+        .args([&address, &upstream])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut client = None;
+    for _ in 0..50 {
+        if let Ok(connected) = Client::connect(&address).await {
+            client = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mut client = client.expect("the proxy never started");
+
+    let success = client.login("Bot").await.unwrap();
+    assert_eq!(success.username, "Bot");
+    assert!(client.recv().await.unwrap().is_none());
+    server.await.unwrap();
+
+    proxy.kill().unwrap();
+    let output = proxy.wait_with_output().unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.contains("< Login ClientLogin(SetCompression"),
+        "{output}"
+    );
+    assert!(output.contains("< Play unknown 027f00"), "{output}");
+}
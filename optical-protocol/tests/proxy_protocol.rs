@@ -0,0 +1,157 @@
+//! Tests for PROXY protocol headers, and for reading them in the listener.
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::SocketAddr,
+    sync::mpsc::Receiver,
+    thread,
+    time::Duration,
+};
+
+use common::connect;
+use optical_protocol::server::{
+    proxy_protocol::{parse_header, ProxyHeader},
+    Connection, ListenerConfig, ProxyProtocol,
+};
+use tokio::runtime::Runtime;
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// A version 2 PROXY header for a TCP over IPv4 connection.
+fn v2_header(source: [u8; 4], port: u16) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    header.extend([0x21, 0x11, 0, 12]);
+    header.extend(source);
+    header.extend([10, 0, 0, 1]);
+    header.extend(port.to_be_bytes());
+    header.extend(25565u16.to_be_bytes());
+    return header;
+}
+
+fn handshake() -> Vec<u8> {
+    return common::handshake(1);
+}
+
+#[test]
+fn v1_headers() {
+    let header = b"PROXY TCP4 192.0.2.1 10.0.0.1 51000 25565\r\n";
+    let mut buf = header.to_vec();
+    buf.extend(handshake());
+    assert_eq!(
+        parse_header(&buf).unwrap(),
+        ProxyHeader::Present {
+            length: header.len(),
+            source: Some("192.0.2.1:51000".parse().unwrap()),
+        }
+    );
+
+    let header = b"PROXY TCP6 2001:db8::1 2001:db8::2 51000 25565\r\n";
+    assert_eq!(
+        parse_header(header).unwrap(),
+        ProxyHeader::Present {
+            length: header.len(),
+            source: Some("[2001:db8::1]:51000".parse().unwrap()),
+        }
+    );
+
+    let header = b"PROXY UNKNOWN\r\n";
+    assert_eq!(
+        parse_header(header).unwrap(),
+        ProxyHeader::Present {
+            length: header.len(),
+            source: None,
+        }
+    );
+
+    assert_eq!(
+        parse_header(b"PROXY TCP4 192.0.2.1").unwrap(),
+        ProxyHeader::Incomplete
+    );
+    assert_eq!(parse_header(b"PRO").unwrap(), ProxyHeader::Incomplete);
+    assert!(parse_header(b"PROXY TCP4 2001:db8::1 10.0.0.1 1 2\r\n").is_err());
+    assert!(parse_header(b"PROXY TCP4 192.0.2.1\r\n").is_err());
+    let mut too_long = b"PROXY ".to_vec();
+    too_long.resize(200, b'1');
+    assert!(parse_header(&too_long).is_err());
+}
+
+#[test]
+fn v2_headers() {
+    let header = v2_header([192, 0, 2, 1], 51000);
+    assert_eq!(
+        parse_header(&header).unwrap(),
+        ProxyHeader::Present {
+            length: 28,
+            source: Some("192.0.2.1:51000".parse().unwrap()),
+        }
+    );
+    assert_eq!(
+        parse_header(&header[..20]).unwrap(),
+        ProxyHeader::Incomplete
+    );
+    assert_eq!(parse_header(&header[..5]).unwrap(), ProxyHeader::Incomplete);
+
+    // A LOCAL header, with a TLV the parser skips.
+    let mut local = V2_SIGNATURE.to_vec();
+    local.extend([0x20, 0x00, 0, 4, 0x04, 0, 1, 0]);
+    assert_eq!(
+        parse_header(&local).unwrap(),
+        ProxyHeader::Present {
+            length: 20,
+            source: None,
+        }
+    );
+
+    let mut version_one = header.clone();
+    version_one[12] = 0x11;
+    assert!(parse_header(&version_one).is_err());
+    let mut short = header;
+    short[15] = 4;
+    assert!(parse_header(&short).is_err());
+}
+
+#[test]
+fn no_header() {
+    assert_eq!(parse_header(&handshake()).unwrap(), ProxyHeader::Absent);
+    assert_eq!(parse_header(&[0xfe, 0x01]).unwrap(), ProxyHeader::Absent);
+}
+
+fn listener(proxy_protocol: ProxyProtocol) -> (Runtime, Receiver<Connection>, SocketAddr) {
+    return common::listener(ListenerConfig {
+        proxy_protocol,
+        ..Default::default()
+    });
+}
+
+#[test]
+fn listener_reads_the_client_address() {
+    let (_runtime, connections, address) = listener(ProxyProtocol::Required);
+    let mut stream = connect(address);
+    let mut sent = v2_header([192, 0, 2, 1], 51000);
+    sent.extend(handshake());
+    // Split the header, as a load balancer may.
+    stream.write_all(&sent[..10]).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(&sent[10..]).unwrap();
+
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    let expected: SocketAddr = "192.0.2.1:51000".parse().unwrap();
+    assert_eq!(connection.address, expected);
+
+    // Without a header, the connection is closed before the handshake reaches anyone.
+    let mut stream = connect(address);
+    stream.write_all(&handshake()).unwrap();
+    assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+    assert!(connections.try_recv().is_err());
+}
+
+#[test]
+fn optional_header() {
+    let (_runtime, connections, address) = listener(ProxyProtocol::Optional);
+    let mut stream = connect(address);
+    stream.write_all(&handshake()).unwrap();
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(connection.address, stream.local_addr().unwrap());
+}
@@ -0,0 +1,681 @@
+//! Property based tests asserting that every value survives a trip through the serializer and back
+//! through the deserializer unchanged.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Cursor,
+};
+
+use indexmap::IndexMap;
+use optical_protocol::{
+    format::{
+        deserializer::{self, TrailingBytesMode},
+        error::Error,
+        serializer,
+        tags::{
+            ClientLoginPacket, ClientPlayPacket, ClientStatusPacket, LoginPacket, PlayPacket,
+            StatusPacket, VoidPacket,
+        },
+        types::{
+            Bytes, ConditionalOn, Json, MinecraftUuid, OptionalVarInt, TrailingOption, VarInt,
+            VarLong,
+        },
+    },
+    packets::{
+        clientbound,
+        login::{
+            clientbound::{
+                Disconnect, EncryptionRequest, LoginPluginRequest, LoginSuccess,
+                LoginSuccessProperty, SetCompression,
+            },
+            serverbound::{EncryptionResponse, LoginPluginResponse, LoginStart},
+        },
+        serverbound,
+        status::{
+            clientbound::{
+                PingResponse, ServerStatus, StatusPlayerSample, StatusPlayers, StatusResponse,
+                StatusVersion,
+            },
+            serverbound::{PingRequest, StatusRequest},
+        },
+        void::serverbound::Handshake,
+        GameMode, Hand,
+    },
+};
+use proptest::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Serializes `value` as a packet, then deserializes it as the same concrete type.
+fn roundtrip<T>(value: &T) -> T
+where
+    T: Serialize + DeserializeOwned,
+{
+    return roundtrip_as(value);
+}
+
+/// Serializes `value` as a packet, then deserializes it as `T`.
+fn roundtrip_as<T>(value: &impl Serialize) -> T
+where
+    T: DeserializeOwned,
+{
+    let bytes = serializer::to_bytes(value, 0).unwrap();
+    return deserializer::from_bytes_with_mode(&mut Cursor::new(bytes), TrailingBytesMode::Strict)
+        .unwrap();
+}
+
+/// Serializes `value` as a packet, then deserializes it as the boxed packet trait `B` through
+/// typetag.
+fn roundtrip_generic<B>(value: &impl Serialize, packet_id: i32) -> Box<B>
+where
+    B: ?Sized,
+    Box<B>: DeserializeOwned,
+{
+    let bytes = serializer::to_bytes(value, packet_id).unwrap();
+    return deserializer::from_bytes_generic_with_mode(
+        &mut Cursor::new(bytes),
+        TrailingBytesMode::Strict,
+    )
+    .unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct Primitives {
+    boolean: bool,
+    i8: i8,
+    i16: i16,
+    i32: i32,
+    i64: i64,
+    u8: u8,
+    u16: u16,
+    u32: u32,
+    u64: u64,
+    f32: f32,
+    f64: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+enum Shape {
+    Unit,
+    Newtype(i32),
+    Tuple(u8, String),
+    Struct { flag: bool, value: Option<i64> },
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+struct Composite {
+    string: String,
+    option: Option<u16>,
+    sequence: Vec<String>,
+    tuple: (i32, bool, String),
+    shapes: Vec<Shape>,
+    var_int: VarInt,
+    var_long: VarLong,
+    uuid: MinecraftUuid,
+    hash_map: HashMap<String, i32>,
+    btree_map: BTreeMap<VarInt, Vec<String>>,
+    index_map: IndexMap<String, Option<bool>>,
+    // Byte buffers consume the rest of the packet, so they must come last.
+    bytes: Bytes,
+}
+
+fn primitives() -> impl Strategy<Value = Primitives> {
+    return (
+        (any::<bool>(), any::<i8>(), any::<i16>(), any::<i32>()),
+        (any::<i64>(), any::<u8>(), any::<u16>(), any::<u32>()),
+        (any::<u64>(), any::<f32>(), any::<f64>()),
+    )
+        .prop_map(
+            |((boolean, i8, i16, i32), (i64, u8, u16, u32), (u64, f32, f64))| Primitives {
+                boolean,
+                i8,
+                i16,
+                i32,
+                i64,
+                u8,
+                u16,
+                u32,
+                u64,
+                f32,
+                f64,
+            },
+        );
+}
+
+fn shape() -> impl Strategy<Value = Shape> {
+    return prop_oneof![
+        Just(Shape::Unit),
+        any::<i32>().prop_map(Shape::Newtype),
+        (any::<u8>(), any::<String>()).prop_map(|(a, b)| Shape::Tuple(a, b)),
+        (any::<bool>(), any::<Option<i64>>())
+            .prop_map(|(flag, value)| Shape::Struct { flag, value }),
+    ];
+}
+
+fn var_int() -> impl Strategy<Value = VarInt> {
+    return any::<i32>().prop_map(VarInt::from);
+}
+
+fn var_long() -> impl Strategy<Value = VarLong> {
+    return any::<i64>().prop_map(VarLong::from);
+}
+
+fn uuid() -> impl Strategy<Value = MinecraftUuid> {
+    return any::<u128>().prop_map(|n| MinecraftUuid(uuid::Uuid::from_u128(n)));
+}
+
+fn bytes() -> impl Strategy<Value = Bytes> {
+    return any::<Vec<u8>>().prop_map(Bytes);
+}
+
+fn composite() -> impl Strategy<Value = Composite> {
+    return (
+        (any::<String>(), any::<Option<u16>>(), any::<Vec<String>>()),
+        (
+            any::<(i32, bool, String)>(),
+            prop::collection::vec(shape(), 0..8),
+        ),
+        (
+            any::<HashMap<String, i32>>(),
+            prop::collection::btree_map(var_int(), any::<Vec<String>>(), 0..8),
+            any::<Vec<(String, Option<bool>)>>(),
+        ),
+        (var_int(), var_long(), uuid(), bytes()),
+    )
+        .prop_map(
+            |(
+                (string, option, sequence),
+                (tuple, shapes),
+                (hash_map, btree_map, index_map),
+                (var_int, var_long, uuid, bytes),
+            )| {
+                Composite {
+                    hash_map,
+                    btree_map,
+                    index_map: index_map.into_iter().collect(),
+                    string,
+                    option,
+                    sequence,
+                    tuple,
+                    shapes,
+                    var_int,
+                    var_long,
+                    uuid,
+                    bytes,
+                }
+            },
+        );
+}
+
+fn server_status() -> impl Strategy<Value = ServerStatus> {
+    let players = (any::<(i32, i32)>(), any::<Vec<(String, String)>>()).prop_map(
+        |((max, online), sample)| StatusPlayers {
+            max,
+            online,
+            sample: sample
+                .into_iter()
+                .map(|(name, id)| StatusPlayerSample { name, id })
+                .collect(),
+        },
+    );
+    return (
+        any::<(String, i32)>(),
+        prop::option::of(players),
+        any::<Option<String>>(),
+        any::<Option<String>>(),
+        any::<Option<bool>>(),
+    )
+        .prop_map(
+            |((name, protocol), players, motd, favicon, enforces_secure_chat)| ServerStatus {
+                version: StatusVersion { name, protocol },
+                players,
+                description: motd.map(|text| serde_json::json!({ "text": text })),
+                favicon,
+                enforces_secure_chat,
+            },
+        );
+}
+
+fn login_success_properties() -> impl Strategy<Value = Vec<LoginSuccessProperty>> {
+    return prop::collection::vec(
+        any::<(String, String, Option<String>)>().prop_map(|(name, value, signature)| {
+            LoginSuccessProperty {
+                name,
+                value,
+                signature,
+            }
+        }),
+        0..4,
+    );
+}
+
+proptest! {
+    #[test]
+    fn primitives_roundtrip(value in primitives()) {
+        prop_assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn shapes_roundtrip(value in shape()) {
+        prop_assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn composite_roundtrip(value in composite()) {
+        prop_assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn var_int_roundtrip(value in var_int()) {
+        let decoded = roundtrip(&value);
+        prop_assert_eq!(decoded.size, VarInt::encoded_size(value.value));
+        prop_assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn var_long_roundtrip(value in var_long()) {
+        let decoded = roundtrip(&value);
+        prop_assert_eq!(decoded.size, VarLong::encoded_size(value.value));
+        prop_assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn handshake_roundtrip(
+        protocol_version in var_int(),
+        server_address in any::<String>(),
+        server_port in any::<u16>(),
+        next_state in var_int(),
+    ) {
+        let packet = Handshake { protocol_version, server_address, server_port, next_state };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn VoidPacket>(&packet, 0);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+    }
+
+    #[test]
+    fn status_roundtrip(status in server_status(), payload in any::<i64>()) {
+        let packet = StatusResponse { status: Json(status) };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientStatusPacket>(&packet, 0);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = PingResponse { payload };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientStatusPacket>(&packet, 1);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = StatusRequest {};
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn StatusPacket>(&packet, 0);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = PingRequest { payload };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn StatusPacket>(&packet, 1);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+    }
+
+    #[test]
+    fn clientbound_login_roundtrip(
+        reason in any::<String>(),
+        server_id in any::<String>(),
+        public_key in any::<Vec<u8>>(),
+        verify_token in any::<Vec<u8>>(),
+        uuid in uuid(),
+        username in any::<String>(),
+        properties in login_success_properties(),
+        threshold in var_int(),
+        message_id in var_int(),
+        channel in any::<String>(),
+        data in bytes(),
+    ) {
+        let packet = Disconnect { reason };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientLoginPacket>(&packet, 0);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = EncryptionRequest { server_id, public_key, verify_token };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientLoginPacket>(&packet, 1);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = LoginSuccess { uuid, username, properties };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientLoginPacket>(&packet, 2);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = SetCompression { threshold };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientLoginPacket>(&packet, 3);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = LoginPluginRequest { message_id, channel, data };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientLoginPacket>(&packet, 4);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+    }
+
+    #[test]
+    fn serverbound_login_roundtrip(
+        name in any::<String>(),
+        player_uuid in prop::option::of(prop::option::of(uuid())),
+        shared_secret in any::<Vec<u8>>(),
+        verify_token in any::<Vec<u8>>(),
+        message_id in var_int(),
+        data in prop::option::of(bytes()),
+    ) {
+        let packet = LoginStart { name, player_uuid: TrailingOption(player_uuid) };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn LoginPacket>(&packet, 0);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = EncryptionResponse { shared_secret, verify_token };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn LoginPacket>(&packet, 1);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = LoginPluginResponse { message_id, data };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn LoginPacket>(&packet, 2);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+    }
+
+    #[test]
+    fn plugin_message_roundtrip(channel in any::<String>(), data in bytes()) {
+        let packet = clientbound::PluginMessage { channel: channel.clone(), data: data.clone() };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientPlayPacket>(&packet, 0x15);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = serverbound::PluginMessage { channel, data };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn PlayPacket>(&packet, 0x0c);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+    }
+
+    #[test]
+    fn play_connection_roundtrip(reason in any::<String>(), id in any::<i64>()) {
+        let packet = clientbound::Disconnect { reason };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientPlayPacket>(&packet, 0x17);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = clientbound::KeepAlive { id };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn ClientPlayPacket>(&packet, 0x1f);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+
+        let packet = serverbound::KeepAlive { id };
+        prop_assert_eq!(&roundtrip(&packet), &packet);
+        let decoded = roundtrip_generic::<dyn PlayPacket>(&packet, 0x11);
+        prop_assert_eq!(decoded.as_any().downcast_ref(), Some(&packet));
+    }
+}
+
+#[test]
+fn batched_packets_roundtrip() {
+    let packets: Vec<_> = [0, 127, 128, 20000]
+        .into_iter()
+        .map(|size| LoginPluginRequest {
+            message_id: size.into(),
+            channel: "optical:batch".to_string(),
+            data: Bytes(vec![7; size as usize]),
+        })
+        .collect();
+
+    let mut buf = bytes::BytesMut::new();
+    for packet in &packets {
+        serializer::encode_into(packet, 4, &mut buf).unwrap();
+    }
+
+    let mut buf = buf.to_vec();
+    for packet in &packets {
+        let mut framed = optical_protocol::server::split_packet(&mut buf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(framed.get_ref(), &serializer::to_bytes(packet, 4).unwrap());
+        let decoded: LoginPluginRequest =
+            deserializer::from_bytes_with_mode(&mut framed, TrailingBytesMode::Strict).unwrap();
+        assert_eq!(&decoded, packet);
+    }
+    assert!(buf.is_empty());
+}
+
+#[derive(Serialize)]
+struct IterPacket<I: Iterator<Item = String> + Clone> {
+    before: u8,
+    #[serde(serialize_with = "optical_protocol::format::counted::serialize")]
+    names: I,
+    after: VarInt,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct CollectedPacket {
+    before: u8,
+    #[serde(deserialize_with = "optical_protocol::format::counted::deserialize")]
+    names: std::collections::VecDeque<String>,
+    after: VarInt,
+}
+
+/// A sequence which doesn't tell the serializer its length up front.
+struct Unsized(Vec<Vec<u16>>);
+
+impl Serialize for Unsized {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+
+        let mut seq = serializer.serialize_seq(None)?;
+        for element in &self.0 {
+            seq.serialize_element(element)?;
+        }
+        return seq.end();
+    }
+}
+
+proptest! {
+    #[test]
+    fn unsized_seq_roundtrip(value in any::<Vec<Vec<u16>>>(), after in any::<String>()) {
+        let bytes = serializer::to_bytes(&(Unsized(value.clone()), &after), 0).unwrap();
+        prop_assert_eq!(&bytes, &serializer::to_bytes(&(&value, &after), 0).unwrap());
+        let decoded: (Vec<Vec<u16>>, String) = deserializer::from_bytes_with_mode(
+            &mut Cursor::new(bytes),
+            TrailingBytesMode::Strict,
+        )
+        .unwrap();
+        prop_assert_eq!(decoded, (value, after));
+    }
+
+    #[test]
+    fn counted_iter_roundtrip(names in any::<Vec<String>>(), min_len in 0usize..16) {
+        let packet = IterPacket {
+            before: 1,
+            names: names.iter().filter(|n| n.len() >= min_len).cloned(),
+            after: 300.into(),
+        };
+        let decoded: CollectedPacket = roundtrip_as(&packet);
+        prop_assert_eq!(decoded, CollectedPacket {
+            before: 1,
+            names: names.into_iter().filter(|n| n.len() >= min_len).collect(),
+            after: 300.into(),
+        });
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct OptionalFields {
+    entity: OptionalVarInt,
+    signature: ConditionalOn<bool, (i64, Vec<u8>)>,
+    properties: ConditionalOn<VarInt, String>,
+    trailing: TrailingOption<(String, Option<VarInt>)>,
+}
+
+proptest! {
+    #[test]
+    fn optional_fields_roundtrip(
+        entity in prop::option::of(any::<i32>().prop_filter("not zero", |n| *n != 0)),
+        signature in prop::option::of(any::<(i64, Vec<u8>)>()),
+        properties in prop::option::of((any::<i32>().prop_filter("not zero", |n| *n != 0), any::<String>())),
+        trailing in prop::option::of(any::<(String, Option<i32>)>()),
+    ) {
+        let value = OptionalFields {
+            entity: OptionalVarInt(entity),
+            signature: ConditionalOn::new(signature.is_some(), signature).unwrap(),
+            properties: match properties {
+                Some((count, value)) => ConditionalOn::new(count.into(), Some(value)).unwrap(),
+                None => ConditionalOn::new(0.into(), None).unwrap(),
+            },
+            trailing: TrailingOption(trailing.map(|(s, n)| (s, n.map(VarInt::from)))),
+        };
+        prop_assert_eq!(roundtrip(&value), value);
+    }
+}
+
+#[test]
+fn optional_field_encodings() {
+    // A missing trailing option takes up no bytes, a var int sentinel is a single 0.
+    let value = (OptionalVarInt(None), TrailingOption::<u8>(None));
+    assert_eq!(serializer::to_bytes(&value, 0).unwrap(), [2, 0, 0]);
+    let value = (OptionalVarInt(Some(1)), TrailingOption(Some(7u8)));
+    assert_eq!(serializer::to_bytes(&value, 0).unwrap(), [3, 0, 1, 7]);
+
+    assert!(serializer::to_bytes(&OptionalVarInt(Some(0)), 0).is_err());
+    let mismatched = ConditionalOn {
+        condition: false,
+        value: Some(1u8),
+    };
+    assert!(serializer::to_bytes(&mismatched, 0).is_err());
+
+    // Old clients end the login start packet after the name.
+    let old_login_start = [7, 0, 5, b'N', b'o', b't', b'c', b'h'];
+    let packet: LoginStart = deserializer::from_bytes_with_mode(
+        &mut Cursor::new(old_login_start.to_vec()),
+        TrailingBytesMode::Strict,
+    )
+    .unwrap();
+    assert_eq!(packet.player_uuid, TrailingOption(None));
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename = "$optical::i32(2, 5)")]
+enum ClickAction {
+    Pickup { slot: i16, button: i8 },
+    Drag(VarInt),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename = "$optical::identifier")]
+enum Dimension {
+    #[serde(rename = "minecraft:overworld")]
+    Overworld,
+    #[serde(rename = "minecraft:the_end")]
+    End,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Indexed {
+    First,
+    Second(u8),
+}
+
+fn game_mode() -> impl Strategy<Value = GameMode> {
+    return prop_oneof![
+        Just(GameMode::Survival),
+        Just(GameMode::Creative),
+        Just(GameMode::Adventure),
+        Just(GameMode::Spectator),
+    ];
+}
+
+fn click_action() -> impl Strategy<Value = ClickAction> {
+    return prop_oneof![
+        any::<(i16, i8)>().prop_map(|(slot, button)| ClickAction::Pickup { slot, button }),
+        var_int().prop_map(ClickAction::Drag),
+    ];
+}
+
+proptest! {
+    #[test]
+    fn enums_roundtrip(
+        game_mode in game_mode(),
+        off_hand in any::<bool>(),
+        click in click_action(),
+        end in any::<bool>(),
+    ) {
+        let hand = if off_hand { Hand::OffHand } else { Hand::MainHand };
+        let dimension = if end { Dimension::End } else { Dimension::Overworld };
+        let value = (game_mode, hand, click, dimension);
+        prop_assert_eq!(roundtrip(&value), value);
+    }
+}
+
+/// Serializes `value` as a packet, and returns the bytes after its length and id.
+fn body(value: &impl Serialize) -> Vec<u8> {
+    return serializer::to_bytes(value, 0).unwrap()[2..].to_vec();
+}
+
+#[test]
+fn sequences_end_at_their_length() {
+    // A sequence ends after its length in elements, leaving the fields after it.
+    let value = (vec![1u8, 2], 3u8, vec!["é✓".to_string()], 4u8);
+    assert_eq!(
+        body(&value),
+        [2, 1, 2, 3, 1, 5, 0xc3, 0xa9, 0xe2, 0x9c, 0x93, 4]
+    );
+    assert_eq!(roundtrip(&value), value);
+}
+
+#[test]
+fn enum_discriminant_encodings() {
+    assert_eq!(body(&GameMode::Spectator), [3]);
+    assert_eq!(body(&Hand::OffHand), [1]);
+    assert_eq!(
+        body(&ClickAction::Drag(300.into())),
+        [0, 0, 0, 5, 0xac, 0x02]
+    );
+    assert_eq!(body(&Dimension::End), b"\x11minecraft:the_end");
+    // Variants of enums listing no ids are sent as their declaration index.
+    assert_eq!(body(&Indexed::Second(7)), [1, 7]);
+
+    // Other formats see the variants by their names.
+    assert_eq!(
+        serde_json::to_string(&GameMode::Spectator).unwrap(),
+        "\"Spectator\""
+    );
+    assert_eq!(
+        serde_json::to_string(&Hand::OffHand).unwrap(),
+        "\"OffHand\""
+    );
+    assert_eq!(
+        serde_json::to_string(&ClickAction::Drag(300.into())).unwrap(),
+        "{\"Drag\":300}"
+    );
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename = "$optical::u8(1, 2)")]
+enum Short {
+    First,
+    Second,
+    Third,
+}
+
+#[test]
+fn enum_markers_list_an_id_per_variant() {
+    let res = serializer::to_bytes(&Short::Third, 0);
+    assert!(matches!(res, Err(Error::InvalidEnumMarker(_))));
+    let res: Result<Short, Error> = deserializer::from_bytes(&mut Cursor::new(vec![2, 0, 3]));
+    assert!(matches!(res, Err(Error::UnknownDiscriminant(3))));
+    assert_eq!(body(&Short::Second), [2]);
+}
+
+#[test]
+fn json_encoding() {
+    let value = Json(vec![1, 2]);
+    assert_eq!(body(&value), b"\x05[1,2]");
+    // Other formats see the inner value.
+    assert_eq!(serde_json::to_string(&value).unwrap(), "[1,2]");
+
+    let invalid = [7, 0, 5, b'[', b'1', b',', b'2', b'['];
+    let res: Result<Json<Vec<i32>>, _> =
+        deserializer::from_bytes(&mut Cursor::new(invalid.to_vec()));
+    assert!(matches!(res, Err(Error::MalformedJson(_))));
+}
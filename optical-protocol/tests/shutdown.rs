@@ -0,0 +1,82 @@
+//! Tests for stopping the listener.
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::mpsc::RecvTimeoutError,
+    thread,
+    time::Duration,
+};
+
+use optical_protocol::{
+    format::{serializer, tags::ClientStatusPacket},
+    packets::{login::clientbound::Disconnect, status::clientbound::PingResponse},
+    server::{ListenerConfig, SHUTDOWN_REASON},
+};
+use tokio::sync::watch;
+
+#[test]
+fn listener_stops_accepting_connections() {
+    let (stop, stopped) = watch::channel(false);
+    let (_runtime, connections, address) = common::listener(ListenerConfig {
+        shutdown: Some(stopped),
+        ..Default::default()
+    });
+
+    let mut stream = common::connect(address);
+    stream.write_all(&common::handshake(1)).unwrap();
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    stop.send(true).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert!(TcpStream::connect(address).is_err());
+
+    // The open connection still works, until its last frames are written.
+    let pong = PingResponse { payload: 42 };
+    let frame = serializer::to_bytes(&pong, pong.packet_id()).unwrap();
+    connection.outgoing.send(frame.clone()).unwrap();
+    assert!(matches!(
+        connections.recv_timeout(Duration::from_millis(100)),
+        Err(RecvTimeoutError::Timeout)
+    ));
+    drop(connection);
+    let mut received = vec![];
+    stream.read_to_end(&mut received).unwrap();
+    assert_eq!(received, frame);
+
+    // The receiver disconnects once every connection closed.
+    assert!(matches!(
+        connections.recv_timeout(Duration::from_secs(5)),
+        Err(RecvTimeoutError::Disconnected)
+    ));
+}
+
+#[test]
+fn clients_not_handed_over_are_disconnected() {
+    let (stop, stopped) = watch::channel(false);
+    let (_runtime, connections, address) = common::listener(ListenerConfig {
+        shutdown: Some(stopped),
+        ..Default::default()
+    });
+
+    // One client waits to send its login start, the other hasn't sent its handshake yet.
+    let mut logging_in = common::connect(address);
+    logging_in.write_all(&common::handshake(2)).unwrap();
+    let mut handshaking = common::connect(address);
+    thread::sleep(Duration::from_millis(100));
+    stop.send(true).unwrap();
+
+    let disconnect: Disconnect = common::read(&mut logging_in, &mut vec![]);
+    assert!(disconnect.reason.contains(SHUTDOWN_REASON));
+    assert!(common::read_until_closed(&mut logging_in).is_empty());
+    // Clients can't be told why before the handshake.
+    assert!(common::read_until_closed(&mut handshaking).is_empty());
+
+    // Long before their timeouts.
+    assert!(matches!(
+        connections.recv_timeout(Duration::from_secs(1)),
+        Err(RecvTimeoutError::Disconnected)
+    ));
+}
@@ -0,0 +1,78 @@
+//! Tests for the deadlines the listener gives clients.
+
+mod common;
+
+use std::{
+    io::Write,
+    net::SocketAddr,
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
+};
+
+use common::{connect, handshake, read_until_closed};
+use optical_protocol::{
+    format::{deserializer, tags::ClientLoginPacket},
+    packets::login::clientbound::{Disconnect, EncryptionRequest},
+    server::{split_packet, Connection, ListenerConfig},
+};
+use tokio::runtime::Runtime;
+
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+fn listener() -> (Runtime, Receiver<Connection>, SocketAddr) {
+    return common::listener(ListenerConfig {
+        handshake_timeout: TIMEOUT,
+        login_timeout: TIMEOUT,
+        status_timeout: TIMEOUT,
+        ..Default::default()
+    });
+}
+
+#[test]
+fn silent_connections_are_closed() {
+    let (_runtime, connections, address) = listener();
+    let started = Instant::now();
+    let mut stream = connect(address);
+    assert!(read_until_closed(&mut stream).is_empty());
+    assert!(started.elapsed() >= TIMEOUT);
+
+    // A handshake sent too slowly doesn't count either.
+    let started = Instant::now();
+    let mut stream = connect(address);
+    stream.write_all(&handshake(1)[..3]).unwrap();
+    assert!(read_until_closed(&mut stream).is_empty());
+    assert!(started.elapsed() >= TIMEOUT);
+    assert!(connections.try_recv().is_err());
+}
+
+#[test]
+fn status_connections_are_closed() {
+    let (_runtime, connections, address) = listener();
+    let mut stream = connect(address);
+    stream.write_all(&handshake(1)).unwrap();
+    let connection = connections.recv_timeout(Duration::from_secs(5)).unwrap();
+    let started = Instant::now();
+    assert!(read_until_closed(&mut stream).is_empty());
+    assert!(started.elapsed() >= TIMEOUT / 2);
+    drop(connection);
+}
+
+#[test]
+fn slow_logins_are_disconnected() {
+    let (_runtime, _connections, address) = listener();
+    let mut stream = connect(address);
+    stream.write_all(&common::login("Notch")).unwrap();
+
+    // The encryption request is never answered.
+    let mut received = read_until_closed(&mut stream);
+    let mut packets = vec![];
+    while let Some(mut frame) = split_packet(&mut received).unwrap() {
+        let packet: Box<dyn ClientLoginPacket> =
+            deserializer::from_bytes_generic(&mut frame).unwrap();
+        packets.push(packet);
+    }
+    assert_eq!(packets.len(), 2);
+    assert!(packets[0].as_any().is::<EncryptionRequest>());
+    let disconnect: &Disconnect = packets[1].as_any().downcast_ref().unwrap();
+    assert!(disconnect.reason.contains("Took too long to log in."));
+}
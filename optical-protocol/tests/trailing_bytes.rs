@@ -0,0 +1,111 @@
+//! Tests for packets whose announced length doesn't match what their type consumes.
+
+use std::io::Cursor;
+
+use optical_protocol::{
+    format::{
+        deserializer::{self, TrailingBytesMode},
+        error::Error,
+        serializer,
+        tags::{LoginPacket, StatusPacket, VoidPacket},
+        types::{Bytes, TrailingOption},
+    },
+    packets::{
+        login::serverbound::{LoginPluginResponse, LoginStart},
+        status::serverbound::PingRequest,
+        void::serverbound::Handshake,
+    },
+};
+
+/// A ping request followed by `extra` bytes, which its length includes.
+fn padded_ping(extra: usize) -> Cursor<Vec<u8>> {
+    let ping = PingRequest { payload: 42 };
+    let mut frame = serializer::to_bytes(&ping, ping.packet_id()).unwrap();
+    frame[0] += extra as u8;
+    frame.resize(frame.len() + extra, 0xaa);
+    return Cursor::new(frame);
+}
+
+#[test]
+fn strict_mode_rejects_trailing_bytes() {
+    let res: Result<PingRequest, Error> =
+        deserializer::from_bytes_with_mode(&mut padded_ping(3), TrailingBytesMode::Strict);
+    assert!(matches!(res, Err(Error::TrailingBytes(3))));
+
+    let res: Result<Box<dyn StatusPacket>, Error> =
+        deserializer::from_bytes_generic_with_mode(&mut padded_ping(1), TrailingBytesMode::Strict);
+    assert!(matches!(res, Err(Error::TrailingBytes(1))));
+
+    let res: Result<PingRequest, Error> =
+        deserializer::from_bytes_with_mode(&mut padded_ping(0), TrailingBytesMode::Strict);
+    assert_eq!(res.unwrap().payload, 42);
+}
+
+#[test]
+fn strict_mode_rejects_short_lengths() {
+    let handshake = Handshake {
+        protocol_version: 761.into(),
+        server_address: "localhost".to_string(),
+        server_port: 25565,
+        next_state: 1.into(),
+    };
+    let mut frame = serializer::to_bytes(&handshake, handshake.packet_id()).unwrap();
+    frame[0] -= 2;
+    let res: Result<Handshake, Error> =
+        deserializer::from_bytes_with_mode(&mut Cursor::new(frame), TrailingBytesMode::Strict);
+    assert!(matches!(
+        res,
+        Err(Error::LengthMismatch {
+            announced: 14,
+            consumed: 16
+        })
+    ));
+}
+
+#[test]
+fn lenient_mode_accepts_trailing_bytes() {
+    let ping: PingRequest =
+        deserializer::from_bytes_with_mode(&mut padded_ping(3), TrailingBytesMode::Lenient)
+            .unwrap();
+    assert_eq!(ping.payload, 42);
+    let ping: PingRequest = deserializer::from_bytes(&mut padded_ping(3)).unwrap();
+    assert_eq!(ping.payload, 42);
+}
+
+#[test]
+fn payloads_reject_trailing_bytes() {
+    let res: Result<i32, Error> = deserializer::from_payload(&mut Cursor::new(vec![0, 0, 0, 1, 2]));
+    assert!(matches!(res, Err(Error::TrailingBytes(1))));
+    let res: Result<i32, Error> = deserializer::from_payload(&mut Cursor::new(vec![0, 0, 0, 1]));
+    assert_eq!(res.unwrap(), 1);
+}
+
+#[test]
+fn packets_end_at_their_length() {
+    // A login start without the UUID, as old clients send it, followed by another packet.
+    let login_start = LoginStart {
+        name: "Notch".to_string(),
+        player_uuid: TrailingOption(None),
+    };
+    let response = LoginPluginResponse {
+        message_id: 1.into(),
+        data: Some(Bytes(vec![1, 2])),
+    };
+    let mut frames = serializer::to_bytes(&login_start, login_start.packet_id()).unwrap();
+    frames.extend(serializer::to_bytes(&response, response.packet_id()).unwrap());
+    frames.extend(serializer::to_bytes(&login_start, login_start.packet_id()).unwrap());
+    let mut input = Cursor::new(frames);
+
+    let first: LoginStart =
+        deserializer::from_bytes_with_mode(&mut input, TrailingBytesMode::Strict).unwrap();
+    assert_eq!(first, login_start);
+    let second: Box<dyn LoginPacket> =
+        deserializer::from_bytes_generic_with_mode(&mut input, TrailingBytesMode::Strict).unwrap();
+    assert_eq!(
+        second.as_any().downcast_ref::<LoginPluginResponse>(),
+        Some(&response)
+    );
+    let third: LoginStart =
+        deserializer::from_bytes_with_mode(&mut input, TrailingBytesMode::Strict).unwrap();
+    assert_eq!(third, login_start);
+}
@@ -0,0 +1,43 @@
+//! A packet decoder compiled to WebAssembly, for inspecting captured traffic in the browser.
+//!
+//! Build it with `wasm-pack build --target web` (or `--target nodejs`), and call [`decode_packet`]
+//! with the protocol state and direction of a frame, and the raw bytes of the frame.
+
+use std::io::Cursor;
+
+use optical_protocol::{
+    inspect,
+    packets::{Direction, ProtocolState},
+};
+use wasm_bindgen::prelude::*;
+
+/// Decodes a single framed packet, length and id included, and returns it rendered as JSON,
+/// tagged with its packet id: `{"type":"0","adjacent":{...}}`.
+///
+/// `state` is one of `handshake`, `status`, `login` or `play`, and `direction` is either
+/// `serverbound` or `clientbound`.
+#[wasm_bindgen(js_name = decodePacket)]
+pub fn decode_packet(state: &str, direction: &str, bytes: &[u8]) -> Result<String, JsError> {
+    let state = parse_state(state)?;
+    let direction = parse_direction(direction)?;
+    let packet = inspect::decode(state, direction, &mut Cursor::new(bytes.to_vec()))?;
+    return Ok(serde_json::to_string(&packet)?);
+}
+
+fn parse_state(state: &str) -> Result<ProtocolState, JsError> {
+    return match state {
+        "handshake" | "void" => Ok(ProtocolState::Void),
+        "status" => Ok(ProtocolState::Status),
+        "login" => Ok(ProtocolState::Login),
+        "play" => Ok(ProtocolState::Play),
+        _ => Err(JsError::new(&format!("unknown protocol state {state}"))),
+    };
+}
+
+fn parse_direction(direction: &str) -> Result<Direction, JsError> {
+    return match direction {
+        "serverbound" => Ok(Direction::Serverbound),
+        "clientbound" => Ok(Direction::Clientbound),
+        _ => Err(JsError::new(&format!("unknown direction {direction}"))),
+    };
+}
@@ -0,0 +1,29 @@
+//! Runs under node with `wasm-pack test --node`.
+
+use optical_protocol_wasm::decode_packet;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn decodes_handshake() {
+    // Protocol 761, "localhost", port 25565, next state 2
+    let frame = [
+        16, 0, 0xf9, 0x05, 9, b'l', b'o', b'c', b'a', b'l', b'h', b'o', b's', b't', 0x63, 0xdd, 2,
+    ];
+    let json = decode_packet("handshake", "serverbound", &frame).unwrap();
+    assert_eq!(
+        json,
+        r#"{"type":"0","adjacent":{"protocol_version":761,"server_address":"localhost","server_port":25565,"next_state":2}}"#
+    );
+}
+
+#[wasm_bindgen_test]
+fn decodes_status_response() {
+    let json = br#"{"version":{"name":"1.19.3","protocol":761}}"#;
+    let mut frame = vec![json.len() as u8 + 2, 0, json.len() as u8];
+    frame.extend(json);
+    let decoded = decode_packet("status", "clientbound", &frame).unwrap();
+    assert_eq!(
+        decoded,
+        r#"{"type":"0","adjacent":{"status":{"version":{"name":"1.19.3","protocol":761}}}}"#
+    );
+}
@@ -0,0 +1,188 @@
+//! Plugin channels, through which mods and proxies talk to the server in the Play state.
+//!
+//! A channel is a type implementing [`Channel`], sent as the payload of plugin messages in the
+//! optical format. [`add_channel`] lets systems read the messages of clients as
+//! [`ChannelMessage`] events, and [`NetworkConnected::send_message`] sends one to a client.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::Cursor,
+};
+
+use anyhow::Result;
+use bevy_ecs::prelude::*;
+use log::{debug, error};
+use optical_protocol::{
+    format::{deserializer, serializer, tags::PlayPacket, types::Bytes},
+    packets::{clientbound, serverbound},
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use unwrap_or::unwrap_some_or;
+
+use crate::net::{NetworkConnected, PacketReceived};
+
+/// The brand the server reports on `minecraft:brand`.
+pub const SERVER_BRAND: &str = "optical";
+
+/// The payload of messages on a plugin channel.
+pub trait Channel: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The namespaced identifier of the channel, like `minecraft:brand`.
+    const IDENTIFIER: &'static str;
+}
+
+/// A message a client sent on the channel of `T`.
+pub struct ChannelMessage<T> {
+    pub target: Entity,
+    pub content: T,
+}
+
+/// The channels the server listens on, which clients are told about with `minecraft:register`.
+#[derive(Resource, Default)]
+pub struct PluginChannels {
+    pub identifiers: BTreeSet<&'static str>,
+}
+
+/// The channels a client listens on, and the brand it reported.
+#[derive(Component, Default, Debug)]
+pub struct ClientChannels {
+    pub registered: HashSet<String>,
+    pub brand: Option<String>,
+}
+
+impl NetworkConnected {
+    /// Sends a message to the client on the channel of `T`.
+    pub fn send_message<T: Channel>(&self, message: &T) -> Result<()> {
+        return self.send(&clientbound::PluginMessage {
+            channel: T::IDENTIFIER.to_string(),
+            data: Bytes(serializer::to_payload(message)?),
+        });
+    }
+}
+
+/// Lets systems read the messages clients send on the channel of `T`, and tells clients the
+/// server listens on it.
+pub fn add_channel<T: Channel>(world: &mut World, stage: &mut SystemStage) {
+    world.insert_resource(Events::<ChannelMessage<T>>::default());
+    world
+        .get_resource_or_insert_with(PluginChannels::default)
+        .identifiers
+        .insert(T::IDENTIFIER);
+    stage
+        .add_system(Events::<ChannelMessage<T>>::update_system)
+        .add_system(receive_messages::<T>);
+}
+
+/// Adds the channels of vanilla, `minecraft:register`, `minecraft:unregister` and
+/// `minecraft:brand`, and the system keeping track of them in [`ClientChannels`].
+pub fn add_default_channels(world: &mut World, stage: &mut SystemStage) {
+    add_channel::<Register>(world, stage);
+    add_channel::<Unregister>(world, stage);
+    add_channel::<Brand>(world, stage);
+    stage.add_system(default_channels);
+}
+
+fn receive_messages<T: Channel>(
+    mut reader: EventReader<PacketReceived<dyn PlayPacket>>,
+    mut writer: EventWriter<ChannelMessage<T>>,
+) {
+    for packet in reader.iter() {
+        let message = unwrap_some_or!(
+            packet
+                .content
+                .as_any()
+                .downcast_ref::<serverbound::PluginMessage>(),
+            continue
+        );
+        if message.channel != T::IDENTIFIER {
+            continue;
+        }
+        match deserializer::from_payload(&mut Cursor::new(message.data.0.clone())) {
+            Ok(content) => writer.send(ChannelMessage {
+                target: packet.target,
+                content,
+            }),
+            Err(e) => error!("Failed deserializing a message on {}: {}", T::IDENTIFIER, e),
+        }
+    }
+}
+
+fn default_channels(
+    mut registers: EventReader<ChannelMessage<Register>>,
+    mut unregisters: EventReader<ChannelMessage<Unregister>>,
+    mut brands: EventReader<ChannelMessage<Brand>>,
+    channels: Res<PluginChannels>,
+    mut query: Query<(&NetworkConnected, &mut ClientChannels)>,
+) {
+    for message in registers.iter() {
+        if let Ok((_, mut client)) = query.get_mut(message.target) {
+            client.registered.extend(message.content.0.iter().cloned());
+        }
+    }
+    for message in unregisters.iter() {
+        if let Ok((_, mut client)) = query.get_mut(message.target) {
+            for channel in &message.content.0 {
+                client.registered.remove(channel);
+            }
+        }
+    }
+    for message in brands.iter() {
+        if let Ok((conn, mut client)) = query.get_mut(message.target) {
+            debug!("A client reported its brand {}.", message.content.0);
+            client.brand = Some(message.content.0.clone());
+
+            // Clients report their brand once they joined, which is when they can take ours.
+            let register = Register(channels.identifiers.iter().map(|c| c.to_string()).collect());
+            let sent = conn
+                .send_message(&register)
+                .and_then(|_| conn.send_message(&Brand(SERVER_BRAND.to_string())));
+            if let Err(e) = sent {
+                error!("Failed sending the server's channels: {}", e);
+            }
+        }
+    }
+}
+
+/// `minecraft:register`, with which each side lists channels it listens on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Register(#[serde(with = "identifiers")] pub Vec<String>);
+
+impl Channel for Register {
+    const IDENTIFIER: &'static str = "minecraft:register";
+}
+
+/// `minecraft:unregister`, with which each side lists channels it stopped listening on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Unregister(#[serde(with = "identifiers")] pub Vec<String>);
+
+impl Channel for Unregister {
+    const IDENTIFIER: &'static str = "minecraft:unregister";
+}
+
+/// `minecraft:brand`, the name of the software of each side.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Brand(pub String);
+
+impl Channel for Brand {
+    const IDENTIFIER: &'static str = "minecraft:brand";
+}
+
+/// Channel identifiers, separated by null bytes and taking up the rest of the payload.
+mod identifiers {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        return Bytes(value.join("\0").into_bytes()).serialize(serializer);
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        let bytes = Bytes::deserialize(deserializer)?;
+        let joined = String::from_utf8(bytes.0).map_err(serde::de::Error::custom)?;
+        return Ok(joined
+            .split('\0')
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect());
+    }
+}
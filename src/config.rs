@@ -0,0 +1,221 @@
+//! The configuration of the server, read from [`CONFIG_FILE`] in the working directory.
+//!
+//! Every setting is optional, and the server runs with the defaults when there's no file.
+
+use std::{collections::HashMap, fs, io, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use optical_protocol::server::{
+    forwarding::Forwarding,
+    limits::{Limits, RateLimit},
+    ListenerConfig, ProtocolState,
+};
+use serde::Deserialize;
+
+use crate::keep_alive::KeepAliveConfig;
+
+/// The file the server reads its configuration from.
+pub const CONFIG_FILE: &str = "optical.json";
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address to listen on, the listener's default when unset.
+    pub address: Option<String>,
+    /// How players joining through a proxy are forwarded, off by default.
+    pub forwarding: ForwardingConfig,
+    /// How long clients have for each step before being connected, the listener's defaults when
+    /// unset.
+    pub timeouts: TimeoutsConfig,
+    /// Limits on connections and their traffic, all off by default.
+    pub limits: LimitsConfig,
+    /// How often clients in Play are checked, the defaults of [`KeepAliveConfig`] when unset.
+    pub keep_alive: KeepAliveSettings,
+}
+
+/// The [`KeepAliveConfig`] of the server, in seconds.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeepAliveSettings {
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+/// The timeouts of the listener, in seconds.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    pub handshake_secs: u64,
+    pub login_secs: u64,
+    pub login_plugin_secs: u64,
+    pub status_secs: u64,
+}
+
+/// The [`Forwarding`] of the listener, tagged by its `mode`.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ForwardingConfig {
+    #[default]
+    Disabled,
+    Legacy {
+        secret: String,
+    },
+    LegacyInsecure,
+    Modern {
+        secret: String,
+    },
+}
+
+/// The [`Limits`] of the listener, with the window in seconds.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub connections_per_ip: Option<usize>,
+    pub connection_window_secs: u64,
+    pub concurrent_per_ip: Option<usize>,
+    pub max_connections: Option<usize>,
+    /// How much connections may send per second, by protocol state.
+    pub packet_rates: HashMap<State, RateLimitConfig>,
+}
+
+/// A protocol state, as named in the configuration.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Handshake,
+    Status,
+    Login,
+    Play,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub packets_per_second: u32,
+    pub bytes_per_second: u64,
+}
+
+impl ServerConfig {
+    /// Reads the configuration from `path`, or returns the defaults if there's no such file.
+    pub fn load(path: &Path) -> Result<ServerConfig> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ServerConfig::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed reading {}", path.display())),
+        };
+        let config: ServerConfig =
+            serde_json::from_str(&text).with_context(|| format!("invalid {}", path.display()))?;
+        config
+            .forwarding
+            .to_forwarding()
+            .validate()
+            .with_context(|| format!("invalid {}", path.display()))?;
+        return Ok(config);
+    }
+
+    /// The options of the listener this configuration sets.
+    pub fn listener_config(&self) -> ListenerConfig {
+        let defaults = ListenerConfig::default();
+        return ListenerConfig {
+            address: self.address.clone().unwrap_or(defaults.address.clone()),
+            forwarding: self.forwarding.to_forwarding(),
+            handshake_timeout: Duration::from_secs(self.timeouts.handshake_secs),
+            login_timeout: Duration::from_secs(self.timeouts.login_secs),
+            login_plugin_timeout: Duration::from_secs(self.timeouts.login_plugin_secs),
+            status_timeout: Duration::from_secs(self.timeouts.status_secs),
+            limits: self.limits.to_limits(),
+            ..defaults
+        };
+    }
+}
+
+impl Default for KeepAliveSettings {
+    fn default() -> Self {
+        let defaults = KeepAliveConfig::default();
+        return KeepAliveSettings {
+            interval_secs: defaults.interval.as_secs(),
+            timeout_secs: defaults.timeout.as_secs(),
+        };
+    }
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        let defaults = ListenerConfig::default();
+        return TimeoutsConfig {
+            handshake_secs: defaults.handshake_timeout.as_secs(),
+            login_secs: defaults.login_timeout.as_secs(),
+            login_plugin_secs: defaults.login_plugin_timeout.as_secs(),
+            status_secs: defaults.status_timeout.as_secs(),
+        };
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        return LimitsConfig {
+            connections_per_ip: None,
+            connection_window_secs: Limits::default().connection_window.as_secs(),
+            concurrent_per_ip: None,
+            max_connections: None,
+            packet_rates: HashMap::new(),
+        };
+    }
+}
+
+impl LimitsConfig {
+    pub fn to_limits(&self) -> Limits {
+        return Limits {
+            connections_per_ip: self.connections_per_ip,
+            connection_window: Duration::from_secs(self.connection_window_secs),
+            concurrent_per_ip: self.concurrent_per_ip,
+            max_connections: self.max_connections,
+            packet_rates: self
+                .packet_rates
+                .iter()
+                .map(|(state, rate)| {
+                    let limit = RateLimit {
+                        packets_per_second: rate.packets_per_second,
+                        bytes_per_second: rate.bytes_per_second,
+                    };
+                    return (state.protocol_state(), limit);
+                })
+                .collect(),
+        };
+    }
+}
+
+impl KeepAliveSettings {
+    pub fn to_config(&self) -> KeepAliveConfig {
+        return KeepAliveConfig {
+            interval: Duration::from_secs(self.interval_secs),
+            timeout: Duration::from_secs(self.timeout_secs),
+        };
+    }
+}
+
+impl ForwardingConfig {
+    pub fn to_forwarding(&self) -> Forwarding {
+        return match self {
+            ForwardingConfig::Disabled => Forwarding::Disabled,
+            ForwardingConfig::Legacy { secret } => Forwarding::Legacy {
+                secret: secret.clone(),
+            },
+            ForwardingConfig::LegacyInsecure => Forwarding::LegacyInsecure,
+            ForwardingConfig::Modern { secret } => Forwarding::Modern {
+                secret: secret.clone(),
+            },
+        };
+    }
+}
+
+impl State {
+    fn protocol_state(self) -> ProtocolState {
+        return match self {
+            State::Handshake => ProtocolState::Void,
+            State::Status => ProtocolState::Status,
+            State::Login => ProtocolState::Login,
+            State::Play => ProtocolState::Play,
+        };
+    }
+}
@@ -0,0 +1,127 @@
+//! Keep alives, which check clients in the Play state are still there and measure their latency.
+//!
+//! Every [`KeepAliveConfig::interval`], clients are sent a keep alive which they answer with the
+//! same id. Clients which don't answer within [`KeepAliveConfig::timeout`], or answer with
+//! another id, are disconnected. [`receive_keep_alives`] runs after [`send_keep_alives`], so an
+//! answer arriving in the tick its client timed out is ignored.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bevy_ecs::prelude::*;
+use log::error;
+use optical_protocol::{
+    format::tags::PlayPacket,
+    packets::{clientbound, serverbound},
+    server::ProtocolState,
+};
+use unwrap_or::unwrap_some_or;
+
+use crate::net::{disconnect, NetworkConnected, PacketReceived};
+
+/// How often keep alives are sent, and how long clients have to answer them.
+#[derive(Resource, Debug, Clone)]
+pub struct KeepAliveConfig {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        return KeepAliveConfig {
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(30),
+        };
+    }
+}
+
+/// The keep alive state of a client.
+#[derive(Component, Debug)]
+pub struct KeepAlive {
+    /// The id and sending time of the keep alive waiting for an answer.
+    pub pending: Option<(i64, Instant)>,
+    /// When the last keep alive was sent, or the client connected.
+    pub last_sent: Instant,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        return KeepAlive {
+            pending: None,
+            last_sent: Instant::now(),
+        };
+    }
+}
+
+/// The round trip time to a client, averaged over its keep alives like vanilla does for the ping
+/// shown in the tab list.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Latency(pub Duration);
+
+/// Sends keep alives when they're due, and disconnects clients which didn't answer in time.
+pub fn send_keep_alives(
+    config: Res<KeepAliveConfig>,
+    mut query: Query<(Entity, &mut NetworkConnected, &mut KeepAlive)>,
+    mut commands: Commands,
+) {
+    let now = Instant::now();
+    for (entity, mut conn, mut keep_alive) in &mut query {
+        if conn.protocol_state != ProtocolState::Play || conn.disconnected {
+            continue;
+        }
+        if let Some((_, sent)) = keep_alive.pending {
+            if now - sent > config.timeout {
+                disconnect(&mut commands, entity, &mut conn, "Timed out");
+            }
+            continue;
+        }
+        if now - keep_alive.last_sent < config.interval {
+            continue;
+        }
+
+        // Like vanilla, the id is the current time in milliseconds.
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        if let Err(e) = conn.send(&clientbound::KeepAlive { id }) {
+            error!("Failed sending a keep alive: {}", e);
+            continue;
+        }
+        keep_alive.pending = Some((id, now));
+        keep_alive.last_sent = now;
+    }
+}
+
+/// Matches the answers of clients to their pending keep alive, and updates their latency.
+pub fn receive_keep_alives(
+    mut reader: EventReader<PacketReceived<dyn PlayPacket>>,
+    mut query: Query<(&mut NetworkConnected, &mut KeepAlive, &mut Latency)>,
+    mut commands: Commands,
+) {
+    for packet in reader.iter() {
+        let answer = unwrap_some_or!(
+            packet
+                .content
+                .as_any()
+                .downcast_ref::<serverbound::KeepAlive>(),
+            continue
+        );
+        let (mut conn, mut keep_alive, mut latency) =
+            unwrap_some_or!(query.get_mut(packet.target).ok(), continue);
+        if conn.disconnected {
+            continue;
+        }
+        match keep_alive.pending {
+            Some((id, sent)) if id == answer.id => {
+                keep_alive.pending = None;
+                latency.0 = (latency.0 * 3 + sent.elapsed()) / 4;
+            }
+            _ => disconnect(
+                &mut commands,
+                packet.target,
+                &mut conn,
+                "Answered a keep alive which wasn't sent",
+            ),
+        }
+    }
+}
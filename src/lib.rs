@@ -0,0 +1,10 @@
+//! The optical Minecraft server.
+//!
+//! The binary lives in `main.rs`. Systems and components are exported here so they can be reused
+//! by benchmarks and other tools.
+
+pub mod channels;
+pub mod config;
+pub mod keep_alive;
+pub mod net;
+pub mod shutdown;
@@ -1,20 +1,27 @@
-mod net;
-
-use std::{sync::Mutex, time};
+use std::{path::Path, process::ExitCode, sync::Mutex, time};
 
 use anyhow::Result;
 
 use bevy_ecs::prelude::*;
 use optical_protocol::{
     format::tags::{LoginPacket, PlayPacket, StatusPacket, VoidPacket},
-    server,
+    server::{self, ListenerConfig},
 };
 use simplelog::*;
 use tokio::runtime::Builder;
 
-use crate::net::{accept_connections, packet_broadcaster, ConnectionReceiver, PacketReceived};
+use optical::channels;
+use optical::config::{ServerConfig, CONFIG_FILE};
+use optical::keep_alive::{receive_keep_alives, send_keep_alives};
+use optical::net::{
+    self, accept_connections, packet_broadcaster, ConnectionReceiver, PacketReceived,
+};
+use optical::shutdown::{self, shutdown_schedule};
+
+/// How long clients have to receive their last frames when the server stops.
+const DRAIN_TIMEOUT: time::Duration = time::Duration::from_secs(5);
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     // Create the logger
     TermLogger::init(
         LevelFilter::Debug,
@@ -34,8 +41,18 @@ fn main() -> Result<()> {
     // Create new world
     let mut world = World::new();
 
+    // Stop on SIGINT and SIGTERM
+    let shutdown = shutdown::listen_for_signals(&runtime);
+
     // Spawn the network listener
-    let connection_receiver = server::start(&mut runtime)?;
+    let config = ServerConfig::load(Path::new(CONFIG_FILE))?;
+    let connection_receiver = server::start_with_config(
+        &mut runtime,
+        ListenerConfig {
+            shutdown: Some(shutdown.clone()),
+            ..config.listener_config()
+        },
+    )?;
     world.insert_resource(ConnectionReceiver {
         connections: Mutex::new(connection_receiver),
     });
@@ -46,6 +63,9 @@ fn main() -> Result<()> {
     world.insert_resource(Events::<PacketReceived<dyn LoginPacket>>::default());
     world.insert_resource(Events::<PacketReceived<dyn PlayPacket>>::default());
 
+    // Check clients are still there
+    world.insert_resource(config.keep_alive.to_config());
+
     // Spawn the tick counter
     world.insert_resource(TickCounter::default());
 
@@ -57,22 +77,26 @@ fn main() -> Result<()> {
     pub struct UpdateLabel;
 
     // Add a Stage to the schedule. Add each system here
-    schedule.add_stage(
-        UpdateLabel,
-        SystemStage::parallel()
-            .with_system(update_tick_counter)
-            .with_system(accept_connections)
-            .with_system(packet_broadcaster)
-            .with_system(Events::<PacketReceived<dyn VoidPacket>>::update_system)
-            .with_system(Events::<PacketReceived<dyn StatusPacket>>::update_system)
-            .with_system(Events::<PacketReceived<dyn LoginPacket>>::update_system)
-            .with_system(Events::<PacketReceived<dyn PlayPacket>>::update_system)
-            .with_system(net::system1),
-    );
+    let mut stage = SystemStage::parallel()
+        .with_system(update_tick_counter)
+        .with_system(accept_connections)
+        .with_system(packet_broadcaster)
+        .with_system(Events::<PacketReceived<dyn VoidPacket>>::update_system)
+        .with_system(Events::<PacketReceived<dyn StatusPacket>>::update_system)
+        .with_system(Events::<PacketReceived<dyn LoginPacket>>::update_system)
+        .with_system(Events::<PacketReceived<dyn PlayPacket>>::update_system)
+        .with_system(net::system1)
+        .with_system(send_keep_alives)
+        .with_system(receive_keep_alives.after(send_keep_alives));
+    channels::add_default_channels(&mut world, &mut stage);
+    schedule.add_stage(UpdateLabel, stage);
+
+    // Systems run once when the server stops, like saving the world, are added here
+    let mut shutdown_schedule = shutdown_schedule();
 
     // Run all systems
     let min_tick_duration = time::Duration::from_secs_f32(1. / 20.);
-    loop {
+    while !*shutdown.borrow() {
         let before_tick = time::Instant::now();
         schedule.run(&mut world);
         let tick_duration = before_tick.elapsed();
@@ -88,7 +112,14 @@ fn main() -> Result<()> {
         }
     }
 
-    return Ok(());
+    // Send everyone away, and give the listener time to write what's left
+    shutdown_schedule.run(&mut world);
+    if !shutdown::drain(world.resource::<ConnectionReceiver>(), DRAIN_TIMEOUT) {
+        warn!("Some connections didn't close in time.");
+        return Ok(ExitCode::FAILURE);
+    }
+    info!("Stopped.");
+    return Ok(ExitCode::SUCCESS);
 }
 
 #[derive(Resource, Default)]
@@ -1,20 +1,32 @@
+use anyhow::{anyhow, Result};
 use bevy_ecs::prelude::*;
-use log::error;
+use log::{error, info};
 use optical_protocol::{
     format::{
-        deserializer,
-        tags::{LoginPacket, PlayPacket, StatusPacket, VoidPacket},
+        deserializer::{self, TrailingBytesMode},
+        serializer,
+        tags::{
+            ClientLoginPacket, ClientPlayPacket, LoginPacket, PlayPacket, StatusPacket, VoidPacket,
+        },
     },
-    packets::void::serverbound::Handshake,
-    server::{Connection, ProtocolState},
+    packets::{clientbound, login, void::serverbound::Handshake},
+    server::{forwarding::ForwardedPlayer, Connection, ProtocolState},
 };
+use serde::Serialize;
 use std::{
     io::Cursor,
+    net::SocketAddr,
     sync::{
         mpsc::{Receiver, TryRecvError},
         Mutex,
     },
 };
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    channels::ClientChannels,
+    keep_alive::{KeepAlive, Latency},
+};
 
 #[derive(Resource)]
 pub struct ConnectionReceiver {
@@ -24,7 +36,12 @@ pub struct ConnectionReceiver {
 pub fn accept_connections(receiver: ResMut<ConnectionReceiver>, mut commands: Commands) {
     let receiver = receiver.connections.lock().unwrap();
     while let Ok(conn) = receiver.try_recv() {
-        commands.spawn::<NetworkConnected>(conn.into());
+        commands.spawn((
+            NetworkConnected::from(conn),
+            ClientChannels::default(),
+            KeepAlive::default(),
+            Latency::default(),
+        ));
     }
 }
 
@@ -34,31 +51,95 @@ pub struct NetworkConnected {
     /// This component will not be accessed in parallel due to how ECS works. The Mutex is only here
     /// to make `Reciever` sendable through threads.
     pub packets: Mutex<Receiver<Cursor<Vec<u8>>>>,
+    /// Frames written to the client by the listener.
+    pub outgoing: UnboundedSender<Vec<u8>>,
+    /// How packets with a length not matching their deserialized contents are handled for this
+    /// connection.
+    pub trailing_bytes: TrailingBytesMode,
+    /// The address of the client. Behind a load balancer sending PROXY headers, this is the
+    /// address of the client it forwards, not of the load balancer.
+    pub address: SocketAddr,
+    /// The player a proxy forwarded, when the server runs behind one.
+    pub player: Option<ForwardedPlayer>,
+    /// Whether the client was sent away. Its entity is only despawned once the commands of the
+    /// stage are applied, so systems running meanwhile skip it.
+    pub disconnected: bool,
 }
 
 impl From<Connection> for NetworkConnected {
     fn from(value: Connection) -> Self {
         return NetworkConnected {
-            protocol_state: value.0,
-            packets: Mutex::new(value.1),
+            protocol_state: value.state,
+            packets: Mutex::new(value.packets),
+            outgoing: value.outgoing,
+            trailing_bytes: TrailingBytesMode::default(),
+            address: value.address,
+            player: value.player,
+            disconnected: false,
         };
     }
 }
 
+impl NetworkConnected {
+    /// Sends a play packet to the client.
+    pub fn send<P: ClientPlayPacket + Serialize>(&self, packet: &P) -> Result<()> {
+        return self.send_frame(serializer::to_bytes(packet, packet.packet_id())?);
+    }
+
+    fn send_frame(&self, frame: Vec<u8>) -> Result<()> {
+        return self
+            .outgoing
+            .send(frame)
+            .map_err(|_| anyhow!("the connection is closed"));
+    }
+}
+
+/// Sends a client away with a plain text reason, and despawns it. The connection is closed once
+/// the reason is written. Clients already sent away aren't sent another reason.
+pub fn disconnect(
+    commands: &mut Commands,
+    entity: Entity,
+    conn: &mut NetworkConnected,
+    reason: &str,
+) {
+    if conn.disconnected {
+        return;
+    }
+    conn.disconnected = true;
+    info!("Disconnecting {}: {}", conn.address, reason);
+    let reason = serde_json::json!({ "text": reason }).to_string();
+    let sent = match conn.protocol_state {
+        ProtocolState::Login => {
+            let packet = login::clientbound::Disconnect { reason };
+            serializer::to_bytes(&packet, packet.packet_id())
+                .map_err(|e| e.into())
+                .and_then(|frame| conn.send_frame(frame))
+        }
+        ProtocolState::Play => conn.send(&clientbound::Disconnect { reason }),
+        // Clients can't be told why before logging in.
+        ProtocolState::Void | ProtocolState::Status => Ok(()),
+    };
+    if let Err(e) = sent {
+        error!("Failed sending a disconnect reason: {}", e);
+    }
+    commands.entity(entity).despawn();
+}
+
 pub struct PacketReceived<T: ?Sized> {
     pub target: Entity,
     pub content: Box<T>,
 }
 
 macro_rules! recv_packet {
-    ($w:ident, $packet:ident, $entity:ident, $stop:literal) => {{
-        let deserialized_packet = match deserializer::from_bytes_generic(&mut $packet) {
-            Ok(n) => n,
-            Err(e) => {
-                error!("Failed deserializing a client's packet: {}", e);
-                continue;
-            }
-        };
+    ($w:ident, $packet:ident, $entity:ident, $mode:expr, $stop:literal) => {{
+        let deserialized_packet =
+            match deserializer::from_bytes_generic_with_mode(&mut $packet, $mode) {
+                Ok(n) => n,
+                Err(e) => {
+                    error!("Failed deserializing a client's packet: {}", e);
+                    continue;
+                }
+            };
         $w.send(PacketReceived {
             target: $entity,
             content: deserialized_packet,
@@ -108,12 +189,20 @@ pub fn packet_broadcaster(
                     // Broadcast void/status/login packets once / tick / client, because a protocol
                     // state switch may occur after each packet, forcing the next packet to go into
                     // the wrong event queue.
-                    ProtocolState::Void => recv_packet!(void_writer, packet, entity, true),
-                    ProtocolState::Status => recv_packet!(status_writer, packet, entity, true),
-                    ProtocolState::Login => recv_packet!(login_writer, packet, entity, true),
+                    ProtocolState::Void => {
+                        recv_packet!(void_writer, packet, entity, conn.trailing_bytes, true)
+                    }
+                    ProtocolState::Status => {
+                        recv_packet!(status_writer, packet, entity, conn.trailing_bytes, true)
+                    }
+                    ProtocolState::Login => {
+                        recv_packet!(login_writer, packet, entity, conn.trailing_bytes, true)
+                    }
                     // The protocol state doesn't switch anymore in the play state. Process packets
                     // in batches.
-                    ProtocolState::Play => recv_packet!(play_writer, packet, entity, false),
+                    ProtocolState::Play => {
+                        recv_packet!(play_writer, packet, entity, conn.trailing_bytes, false)
+                    }
                 },
                 Err(TryRecvError::Disconnected) => {
                     commands.entity(entity).despawn();
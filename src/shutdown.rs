@@ -0,0 +1,97 @@
+//! Stopping the server gracefully, on SIGINT or SIGTERM.
+//!
+//! Once a signal arrives, the listener stops accepting connections and the last tick finishes.
+//! The [`ShutdownStage`]s then run once: every client is sent away, and the systems added to
+//! [`ShutdownStage::Save`] run, like saving the world. Finally [`drain`] waits for the frames
+//! still queued for clients to be written.
+
+use std::{
+    sync::mpsc::RecvTimeoutError,
+    time::{Duration, Instant},
+};
+
+use bevy_ecs::prelude::*;
+use log::info;
+use tokio::{runtime::Runtime, sync::watch};
+
+use crate::net::{disconnect, ConnectionReceiver, NetworkConnected};
+
+/// The reason clients are given when the server stops, the same the listener gives clients
+/// still logging in.
+pub use optical_protocol::server::SHUTDOWN_REASON;
+
+/// The stages of the schedule run once when the server stops.
+#[derive(StageLabel)]
+pub enum ShutdownStage {
+    /// Every client is sent away.
+    Disconnect,
+    /// Systems which should run before the server exits, like saving the world.
+    Save,
+}
+
+/// Returns a receiver which holds true once the process receives SIGINT or SIGTERM.
+pub fn listen_for_signals(runtime: &Runtime) -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    runtime.spawn(async move {
+        let signal = wait_for_signal().await;
+        info!("Received {}, shutting down.", signal);
+        let _ = sender.send(true);
+    });
+    return receiver;
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => return "SIGINT",
+        _ = terminate.recv() => return "SIGTERM",
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    return "SIGINT";
+}
+
+/// Creates the schedule run once when the server stops. Shutdown systems are added to its
+/// [`ShutdownStage::Save`] stage.
+pub fn shutdown_schedule() -> Schedule {
+    let mut schedule = Schedule::default();
+    schedule.add_stage(
+        ShutdownStage::Disconnect,
+        SystemStage::parallel().with_system(disconnect_all),
+    );
+    schedule.add_stage(ShutdownStage::Save, SystemStage::parallel());
+    return schedule;
+}
+
+fn disconnect_all(mut query: Query<(Entity, &mut NetworkConnected)>, mut commands: Commands) {
+    for (entity, mut conn) in &mut query {
+        disconnect(&mut commands, entity, &mut conn, SHUTDOWN_REASON);
+    }
+}
+
+/// Waits for the listener to close every connection, which it does once their last frames are
+/// written. Connections the listener hands over meanwhile are closed right away. Returns whether
+/// every connection closed within the timeout.
+pub fn drain(receiver: &ConnectionReceiver, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let connections = receiver.connections.lock().unwrap();
+    loop {
+        match connections.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Disconnected) => return true,
+            Err(RecvTimeoutError::Timeout) => return false,
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Tests for reading the configuration of the server.
+
+use std::{fs, path::Path, time::Duration};
+
+use optical::{config::ServerConfig, keep_alive::KeepAliveConfig};
+use optical_protocol::server::{
+    forwarding::Forwarding, limits::RateLimit, ListenerConfig, ProtocolState,
+};
+
+#[test]
+fn missing_file_is_the_defaults() {
+    let config = ServerConfig::load(Path::new("does-not-exist.json")).unwrap();
+    assert_eq!(config, ServerConfig::default());
+    assert_eq!(config.listener_config().limits, Default::default());
+    assert_eq!(config.listener_config().address, "0.0.0.0:8080");
+    assert_eq!(config.listener_config().forwarding, Forwarding::Disabled);
+    assert_eq!(
+        config.listener_config().login_timeout,
+        ListenerConfig::default().login_timeout
+    );
+}
+
+#[test]
+fn keep_alives() {
+    let path = std::env::temp_dir().join(format!("optical-keep-alive-{}.json", std::process::id()));
+    fs::write(&path, r#"{ "keep_alive": { "interval_secs": 5 } }"#).unwrap();
+    let config = ServerConfig::load(&path).unwrap().keep_alive.to_config();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.interval, Duration::from_secs(5));
+    assert_eq!(config.timeout, KeepAliveConfig::default().timeout);
+    let defaults = ServerConfig::default().keep_alive.to_config();
+    assert_eq!(defaults.interval, KeepAliveConfig::default().interval);
+}
+
+#[test]
+fn timeouts() {
+    let path = std::env::temp_dir().join(format!("optical-timeouts-{}.json", std::process::id()));
+    fs::write(
+        &path,
+        r#"{ "timeouts": { "handshake_secs": 2, "login_secs": 5, "status_secs": 3 } }"#,
+    )
+    .unwrap();
+    let config = ServerConfig::load(&path).unwrap().listener_config();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.handshake_timeout, Duration::from_secs(2));
+    assert_eq!(config.login_timeout, Duration::from_secs(5));
+    assert_eq!(config.status_timeout, Duration::from_secs(3));
+    assert_eq!(
+        config.login_plugin_timeout,
+        ListenerConfig::default().login_plugin_timeout
+    );
+}
+
+#[test]
+fn address_and_forwarding() {
+    let path = std::env::temp_dir().join(format!("optical-proxy-{}.json", std::process::id()));
+    fs::write(
+        &path,
+        r#"{
+            "address": "127.0.0.1:25577",
+            "forwarding": { "mode": "modern", "secret": "hunter2" }
+        }"#,
+    )
+    .unwrap();
+    let config = ServerConfig::load(&path).unwrap().listener_config();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.address, "127.0.0.1:25577");
+    assert_eq!(
+        config.forwarding,
+        Forwarding::Modern {
+            secret: "hunter2".to_string()
+        }
+    );
+}
+
+#[test]
+fn limits() {
+    let path = std::env::temp_dir().join(format!("optical-config-{}.json", std::process::id()));
+    fs::write(
+        &path,
+        r#"{
+            "limits": {
+                "connections_per_ip": 5,
+                "connection_window_secs": 10,
+                "max_connections": 100,
+                "packet_rates": {
+                    "login": { "packets_per_second": 20, "bytes_per_second": 4096 }
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+    let limits = ServerConfig::load(&path).unwrap().listener_config().limits;
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(limits.connections_per_ip, Some(5));
+    assert_eq!(limits.connection_window, Duration::from_secs(10));
+    assert_eq!(limits.concurrent_per_ip, None);
+    assert_eq!(limits.max_connections, Some(100));
+    assert_eq!(
+        limits.packet_rates[&ProtocolState::Login],
+        RateLimit {
+            packets_per_second: 20,
+            bytes_per_second: 4096,
+        }
+    );
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    let path = std::env::temp_dir().join(format!("optical-typo-{}.json", std::process::id()));
+    for text in [
+        r#"{ "limits": { "max_conections": 100 } }"#,
+        r#"{ "timeouts": { "login": 5 } }"#,
+        r#"{ "keep_alive": { "interval": 5 } }"#,
+    ] {
+        fs::write(&path, text).unwrap();
+        assert!(ServerConfig::load(&path).is_err(), "{text}");
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn empty_forwarding_secrets_are_rejected() {
+    let path = std::env::temp_dir().join(format!("optical-secret-{}.json", std::process::id()));
+    for mode in ["legacy", "modern"] {
+        let text = format!(r#"{{ "forwarding": {{ "mode": "{mode}", "secret": "" }} }}"#);
+        fs::write(&path, text).unwrap();
+        let loaded = ServerConfig::load(&path);
+        assert!(loaded.is_err(), "{mode}");
+    }
+    fs::remove_file(&path).unwrap();
+}
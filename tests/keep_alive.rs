@@ -0,0 +1,181 @@
+//! Tests for keep alives, run on a world with only the keep alive systems.
+
+use std::{
+    io::Cursor,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use bevy_ecs::prelude::*;
+use optical::{
+    keep_alive::{receive_keep_alives, send_keep_alives, KeepAlive, KeepAliveConfig, Latency},
+    net::{NetworkConnected, PacketReceived},
+};
+use optical_protocol::{
+    format::{deserializer, tags::ClientPlayPacket, tags::PlayPacket},
+    packets::{clientbound, serverbound},
+    server::{Connection, ProtocolState},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+#[derive(StageLabel)]
+struct Update;
+
+struct Client {
+    world: World,
+    schedule: Schedule,
+    entity: Entity,
+    frames: UnboundedReceiver<Vec<u8>>,
+}
+
+/// A world with one client in Play, and the keep alive systems ordered like the server does.
+fn client(keep_alive: KeepAlive, latency: Latency) -> Client {
+    let mut world = World::new();
+    world.insert_resource(KeepAliveConfig {
+        interval: Duration::from_secs(15),
+        timeout: Duration::from_millis(100),
+    });
+    world.insert_resource(Events::<PacketReceived<dyn PlayPacket>>::default());
+
+    let (_, packets) = mpsc::channel();
+    let (outgoing, frames) = unbounded_channel();
+    let connection = Connection {
+        state: ProtocolState::Play,
+        packets,
+        outgoing,
+        address: "127.0.0.1:25565".parse().unwrap(),
+        player: None,
+    };
+    let entity = world
+        .spawn((NetworkConnected::from(connection), keep_alive, latency))
+        .id();
+
+    let mut schedule = Schedule::default();
+    schedule.add_stage(
+        Update,
+        SystemStage::parallel()
+            .with_system(send_keep_alives)
+            .with_system(receive_keep_alives.after(send_keep_alives)),
+    );
+    return Client {
+        world,
+        schedule,
+        entity,
+        frames,
+    };
+}
+
+impl Client {
+    /// Delivers a keep alive answer, as the packet broadcaster would.
+    fn answer(&mut self, id: i64) {
+        let target = self.entity;
+        self.world
+            .resource_mut::<Events<PacketReceived<dyn PlayPacket>>>()
+            .send(PacketReceived {
+                target,
+                content: Box::new(serverbound::KeepAlive { id }),
+            });
+    }
+
+    /// The packets the client was sent since the last call.
+    fn received(&mut self) -> Vec<Box<dyn ClientPlayPacket>> {
+        let mut packets = vec![];
+        while let Ok(frame) = self.frames.try_recv() {
+            packets.push(deserializer::from_bytes_generic(&mut Cursor::new(frame)).unwrap());
+        }
+        return packets;
+    }
+
+    fn disconnect_reasons(&mut self) -> Vec<String> {
+        return self
+            .received()
+            .iter()
+            .filter_map(|packet| packet.as_any().downcast_ref::<clientbound::Disconnect>())
+            .map(|disconnect| disconnect.reason.clone())
+            .collect();
+    }
+}
+
+fn pending(id: i64, age: Duration) -> KeepAlive {
+    let sent = Instant::now() - age;
+    return KeepAlive {
+        pending: Some((id, sent)),
+        last_sent: sent,
+    };
+}
+
+#[test]
+fn keep_alives_are_sent_when_due() {
+    let mut client = client(KeepAlive::default(), Latency::default());
+    client.world.resource_mut::<KeepAliveConfig>().interval = Duration::ZERO;
+    client.schedule.run(&mut client.world);
+
+    let received = client.received();
+    assert_eq!(received.len(), 1);
+    let sent = received[0]
+        .as_any()
+        .downcast_ref::<clientbound::KeepAlive>()
+        .unwrap();
+    let keep_alive = client.world.get::<KeepAlive>(client.entity).unwrap();
+    assert_eq!(keep_alive.pending.unwrap().0, sent.id);
+
+    // Nothing more is sent while the answer is pending.
+    client.schedule.run(&mut client.world);
+    assert!(client.received().is_empty());
+}
+
+#[test]
+fn latency_is_smoothed() {
+    let mut client = client(
+        pending(7, Duration::from_millis(50)),
+        Latency(Duration::from_millis(10)),
+    );
+    client.answer(7);
+    client.schedule.run(&mut client.world);
+
+    // A quarter of the new round trip, and three quarters of the previous latency.
+    let latency = client.world.get::<Latency>(client.entity).unwrap().0;
+    assert!(latency >= Duration::from_micros(20_000), "{latency:?}");
+    assert!(latency < Duration::from_micros(30_000), "{latency:?}");
+    let keep_alive = client.world.get::<KeepAlive>(client.entity).unwrap();
+    assert_eq!(keep_alive.pending, None);
+    assert!(client.received().is_empty());
+}
+
+#[test]
+fn mismatched_ids_are_disconnected() {
+    let mut client = client(pending(7, Duration::ZERO), Latency::default());
+    client.answer(8);
+    client.answer(9);
+    client.schedule.run(&mut client.world);
+
+    assert!(client.world.get_entity(client.entity).is_none());
+    let reasons = client.disconnect_reasons();
+    assert_eq!(reasons.len(), 1);
+    assert!(reasons[0].contains("Answered a keep alive which wasn't sent"));
+}
+
+#[test]
+fn unanswered_keep_alives_time_out() {
+    let mut client = client(pending(7, Duration::from_millis(200)), Latency::default());
+    client.schedule.run(&mut client.world);
+
+    assert!(client.world.get_entity(client.entity).is_none());
+    let reasons = client.disconnect_reasons();
+    assert_eq!(reasons.len(), 1);
+    assert!(reasons[0].contains("Timed out"));
+}
+
+#[test]
+fn late_answers_are_ignored() {
+    let mut client = client(pending(7, Duration::from_millis(200)), Latency::default());
+    client.answer(7);
+    client.answer(8);
+    client.schedule.run(&mut client.world);
+
+    // Only the timeout disconnects the client.
+    assert!(client.world.get_entity(client.entity).is_none());
+    let reasons = client.disconnect_reasons();
+    assert_eq!(reasons.len(), 1);
+    assert!(reasons[0].contains("Timed out"));
+}
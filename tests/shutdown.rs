@@ -0,0 +1,226 @@
+//! Tests for stopping the server gracefully.
+
+use std::{
+    any::Any,
+    io::{BufRead, BufReader, Cursor, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy_ecs::prelude::*;
+use optical::{
+    channels::Brand,
+    net::{ConnectionReceiver, NetworkConnected},
+    shutdown::{self, shutdown_schedule, SHUTDOWN_REASON},
+};
+use optical_protocol::{
+    format::{
+        deserializer, serializer,
+        tags::{ClientLoginPacket, ClientPlayPacket, LoginPacket, PlayPacket, VoidPacket},
+        types::{Bytes, TrailingOption},
+    },
+    packets::{clientbound, login, serverbound::PluginMessage, void::serverbound::Handshake},
+    server::{forwarding::ForwardedPlayer, split_packet, Connection, ProtocolState},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use uuid::Uuid;
+
+/// A connection the listener handed over, and the frames sent to it.
+fn connection(state: ProtocolState) -> (Connection, UnboundedReceiver<Vec<u8>>) {
+    let (_, packets) = mpsc::channel();
+    let (outgoing, frames) = unbounded_channel();
+    let connection = Connection {
+        state,
+        packets,
+        outgoing,
+        address: "127.0.0.1:25565".parse().unwrap(),
+        player: None,
+    };
+    return (connection, frames);
+}
+
+#[test]
+fn every_client_is_disconnected() {
+    let mut world = World::new();
+    let (logging_in, mut login_frames) = connection(ProtocolState::Login);
+    let (playing, mut play_frames) = connection(ProtocolState::Play);
+    let (pinging, mut status_frames) = connection(ProtocolState::Status);
+    for connection in [logging_in, playing, pinging] {
+        world.spawn(NetworkConnected::from(connection));
+    }
+
+    shutdown_schedule().run(&mut world);
+
+    assert_eq!(world.query::<&NetworkConnected>().iter(&world).count(), 0);
+    let frame = login_frames.try_recv().unwrap();
+    let packet: Box<dyn ClientLoginPacket> =
+        deserializer::from_bytes_generic(&mut Cursor::new(frame)).unwrap();
+    let disconnect = packet
+        .as_any()
+        .downcast_ref::<login::clientbound::Disconnect>()
+        .unwrap();
+    assert!(disconnect.reason.contains(SHUTDOWN_REASON));
+    let frame = play_frames.try_recv().unwrap();
+    let packet: Box<dyn ClientPlayPacket> =
+        deserializer::from_bytes_generic(&mut Cursor::new(frame)).unwrap();
+    let disconnect = packet
+        .as_any()
+        .downcast_ref::<clientbound::Disconnect>()
+        .unwrap();
+    assert!(disconnect.reason.contains(SHUTDOWN_REASON));
+    // Clients can't be told why outside of login and play.
+    assert!(status_frames.try_recv().is_err());
+}
+
+#[test]
+fn drain_waits_for_the_listener() {
+    let (sender, receiver) = mpsc::channel();
+    let receiver = ConnectionReceiver {
+        connections: Mutex::new(receiver),
+    };
+    let before = Instant::now();
+    assert!(!shutdown::drain(&receiver, Duration::from_millis(100)));
+    assert!(before.elapsed() >= Duration::from_millis(100));
+
+    // Connections handed over meanwhile are closed, and the listener closing is waited for.
+    let (late, mut frames) = connection(ProtocolState::Play);
+    let listener = thread::spawn(move || {
+        sender.send(late).unwrap();
+        thread::sleep(Duration::from_millis(50));
+    });
+    assert!(shutdown::drain(&receiver, Duration::from_secs(5)));
+    listener.join().unwrap();
+    assert!(frames.try_recv().is_err());
+    assert!(frames.blocking_recv().is_none());
+}
+
+const SECRET: &str = "hunter2";
+
+/// Reads packets until one of type `T`, and returns it.
+fn read<P: ?Sized + DeserializePacket, T: Any>(stream: &mut TcpStream, buf: &mut Vec<u8>) -> T {
+    loop {
+        while let Some(mut frame) = split_packet(buf).unwrap() {
+            if let Ok(packet) = P::from_frame(&mut frame).downcast::<T>() {
+                return *packet;
+            }
+        }
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).unwrap();
+        assert_ne!(n, 0, "the server closed the connection");
+        buf.extend(&chunk[..n]);
+    }
+}
+
+/// The packets of a state `read` can skip over.
+trait DeserializePacket {
+    fn from_frame(frame: &mut Cursor<Vec<u8>>) -> Box<dyn Any>;
+}
+
+impl DeserializePacket for dyn ClientLoginPacket {
+    fn from_frame(frame: &mut Cursor<Vec<u8>>) -> Box<dyn Any> {
+        let packet: Box<dyn ClientLoginPacket> = deserializer::from_bytes_generic(frame).unwrap();
+        return packet.into_any();
+    }
+}
+
+impl DeserializePacket for dyn ClientPlayPacket {
+    fn from_frame(frame: &mut Cursor<Vec<u8>>) -> Box<dyn Any> {
+        let packet: Box<dyn ClientPlayPacket> = deserializer::from_bytes_generic(frame).unwrap();
+        return packet.into_any();
+    }
+}
+
+/// Logs a player in through legacy forwarding, and waits until the server has it in Play.
+fn join(address: SocketAddr) -> (TcpStream, Vec<u8>) {
+    let player = ForwardedPlayer {
+        address: "192.0.2.1".parse().unwrap(),
+        uuid: Uuid::from_u128(0x069a79f444e94726a5befca90e38aaf5),
+        username: "Notch".to_string(),
+        properties: vec![],
+    };
+    let handshake = Handshake {
+        protocol_version: 761.into(),
+        server_address: player.to_legacy("localhost", Some(SECRET)).unwrap(),
+        server_port: 25565,
+        next_state: 2.into(),
+    };
+    let login_start = login::serverbound::LoginStart {
+        name: "Notch".to_string(),
+        player_uuid: TrailingOption(Some(None)),
+    };
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut sent = serializer::to_bytes(&handshake, handshake.packet_id()).unwrap();
+    sent.extend(serializer::to_bytes(&login_start, login_start.packet_id()).unwrap());
+    stream.write_all(&sent).unwrap();
+    let mut buf = vec![];
+    let _: login::clientbound::LoginSuccess =
+        read::<dyn ClientLoginPacket, _>(&mut stream, &mut buf);
+
+    // The server answers the brand with its own once the player's entity exists.
+    let brand = PluginMessage {
+        channel: "minecraft:brand".to_string(),
+        data: Bytes(serializer::to_payload(&Brand("vanilla".to_string())).unwrap()),
+    };
+    stream
+        .write_all(&serializer::to_bytes(&brand, brand.packet_id()).unwrap())
+        .unwrap();
+    let _: clientbound::PluginMessage = read::<dyn ClientPlayPacket, _>(&mut stream, &mut buf);
+    return (stream, buf);
+}
+
+#[cfg(unix)]
+#[test]
+fn players_are_disconnected_on_sigterm() {
+    use std::process::{Command, Stdio};
+
+    let dir = std::env::temp_dir().join(format!("optical-shutdown-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("optical.json"),
+        format!(
+            r#"{{
+                "address": "127.0.0.1:0",
+                "forwarding": {{ "mode": "legacy", "secret": "{SECRET}" }}
+            }}"#
+        ),
+    )
+    .unwrap();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_optical"))
+        .current_dir(&dir)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The port the server got is only known from its log.
+    let mut log = BufReader::new(server.stdout.take().unwrap()).lines();
+    let address: SocketAddr = loop {
+        let line = log.next().expect("the server exited").unwrap();
+        if let Some((_, address)) = line.split_once("Listening on ") {
+            let address: String = address
+                .chars()
+                .filter(|c| c.is_ascii_digit() || *c == '.' || *c == ':')
+                .collect();
+            break address.parse().unwrap();
+        }
+    };
+    thread::spawn(move || log.for_each(drop));
+
+    let (mut stream, mut buf) = join(address);
+    let killed = Command::new("kill")
+        .args(["-TERM", &server.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let disconnect: clientbound::Disconnect =
+        read::<dyn ClientPlayPacket, _>(&mut stream, &mut buf);
+    assert!(disconnect.reason.contains(SHUTDOWN_REASON));
+    let status = server.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(status.success(), "the server exited with {status}");
+}